@@ -0,0 +1,227 @@
+use geng::prelude::*;
+
+use pog_paint::{document, export, file_format, Document};
+
+use anyhow::Context as _;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Parser)]
+#[clap(about = "Inspect and convert PogPaint .pp files")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print a summary of the file
+    Info { path: PathBuf },
+    /// Rewrite the file using the current format version
+    Upgrade {
+        path: PathBuf,
+        /// Where to write the upgraded file, defaults to overwriting the input
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write every embedded plane image as a png
+    Extract {
+        path: PathBuf,
+        /// Directory to put the images into, defaults to the current directory
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export as glTF 2.0, binary .glb unless the output ends with .gltf
+    Gltf {
+        path: PathBuf,
+        /// Defaults to the input path with .glb extension
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export as wavefront obj with an mtl and a png per plane
+    Obj {
+        path: PathBuf,
+        /// Directory to put the files into, defaults to the current directory
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn load(path: &Path) -> anyhow::Result<(u8, Document)> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+    let result = Document::load_versioned(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to load {path:?}"))?;
+    Ok(result)
+}
+
+fn info(path: &Path) -> anyhow::Result<()> {
+    let (version, document) = load(path)?;
+    println!("version: {version}");
+    if let Some(scene) = &document.scene {
+        let camera = &scene.camera;
+        println!(
+            "camera: pos {:?}, rot {:.1}, attack {:.1}, distance {}, fov {:.1}",
+            camera.pos,
+            camera.rot.to_degrees(),
+            camera.attack.to_degrees(),
+            camera.distance,
+            camera.fov.to_degrees(),
+        );
+        println!("selected: {:?}", scene.selected);
+        println!("color: {:?}", scene.color);
+        println!("palette: {} colors", scene.palette.len());
+    }
+    println!("groups: {}", document.groups.len());
+    for (index, group) in document.groups.iter().enumerate() {
+        match &group.parent {
+            Some(parent) => println!("group #{index}: {:?} in group #{parent}", group.name),
+            None => println!("group #{index}: {:?}", group.name),
+        }
+    }
+    println!("planes: {}", document.planes.len());
+    for (index, plane) in document.planes.iter().enumerate() {
+        println!("plane #{index}:");
+        if !plane.name.is_empty() {
+            println!("  name: {:?}", plane.name);
+        }
+        println!(
+            "  visible: {}, locked: {}, opacity: {}",
+            plane.visible, plane.locked, plane.opacity,
+        );
+        match &plane.image {
+            None => println!("  image: none"),
+            Some(document::Image::Load(path)) => println!("  image: external {path:?}"),
+            Some(document::Image::Pixels(pixels)) => {
+                println!("  image: {}x{}", pixels.size.x, pixels.size.y)
+            }
+            Some(document::Image::Tiles(tiles)) => println!("  image: {} tiles", tiles.len()),
+            Some(document::Image::Linked(target)) => println!("  image: same as plane #{target}"),
+        }
+        if let Some(bb) = plane.bounding_box() {
+            println!(
+                "  bounds: {}, {} .. {}, {}",
+                bb.min.x, bb.min.y, bb.max.x, bb.max.y,
+            );
+        }
+        println!("  offset: {}, {}", plane.offset.x, plane.offset.y);
+        if let Some(parent) = plane.parent {
+            println!("  group: #{parent}");
+        }
+        println!("  transform:");
+        for row in 0..4 {
+            println!(
+                "    {:10.3} {:10.3} {:10.3} {:10.3}",
+                plane.transform[(row, 0)],
+                plane.transform[(row, 1)],
+                plane.transform[(row, 2)],
+                plane.transform[(row, 3)],
+            );
+        }
+    }
+    Ok(())
+}
+
+fn upgrade(path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let (version, document) = load(path)?;
+    let output = output.unwrap_or(path);
+    if version == file_format::VERSION && output == path {
+        println!("{path:?} is already at version {version}");
+        return Ok(());
+    }
+    // Written next to the output first, so a failure does not leave a truncated file behind
+    let mut temp = output.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let write = || -> anyhow::Result<()> {
+        let file =
+            std::fs::File::create(&temp).with_context(|| format!("Failed to create {temp:?}"))?;
+        let mut writer = std::io::BufWriter::new(file);
+        document.save(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(())
+    };
+    if let Err(e) = write() {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e.context(format!("Failed to write {output:?}")));
+    }
+    std::fs::rename(&temp, output)
+        .with_context(|| format!("Failed to replace {output:?} with {temp:?}"))?;
+    println!(
+        "{path:?}: version {version} -> {} written to {output:?}",
+        file_format::VERSION,
+    );
+    Ok(())
+}
+
+fn extract(path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let (_version, document) = load(path)?;
+    let output = output.unwrap_or(Path::new("."));
+    std::fs::create_dir_all(output)?;
+    let stem = path
+        .file_stem()
+        .map_or("plane".into(), |stem| stem.to_string_lossy());
+    for (index, plane) in document.planes.iter().enumerate() {
+        if let Some(document::Image::Load(path)) = &plane.image {
+            eprintln!("plane #{index} references external image {path:?}, skipping");
+            continue;
+        }
+        if let Some(document::Image::Linked(target)) = &plane.image {
+            eprintln!("plane #{index} shares the image of plane #{target}, skipping");
+            continue;
+        }
+        // Far apart strokes are written as separate parts
+        let parts = plane.flatten();
+        for (part, (_offset, pixels)) in parts.iter().enumerate() {
+            let png = if parts.len() == 1 {
+                output.join(format!("{stem}-{index}.png"))
+            } else {
+                output.join(format!("{stem}-{index}-{part}.png"))
+            };
+            pixels
+                .to_image()
+                .save(&png)
+                .with_context(|| format!("Failed to write {png:?}"))?;
+            println!("{png:?}");
+        }
+    }
+    Ok(())
+}
+
+fn gltf(path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let (_version, document) = load(path)?;
+    let output = output.map_or_else(|| path.with_extension("glb"), Path::to_owned);
+    let data = match output.extension() {
+        Some(ext) if ext == "gltf" => export::gltf::to_gltf(&document),
+        _ => export::gltf::to_glb(&document),
+    };
+    std::fs::write(&output, data).with_context(|| format!("Failed to write {output:?}"))?;
+    println!("{output:?}");
+    Ok(())
+}
+
+fn obj(path: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let (_version, document) = load(path)?;
+    let output = output.unwrap_or(Path::new("."));
+    std::fs::create_dir_all(output)?;
+    let name = path
+        .file_stem()
+        .map_or("model".into(), |stem| stem.to_string_lossy());
+    for (file_name, data) in export::obj::export(&document, &name) {
+        let file_path = output.join(file_name);
+        std::fs::write(&file_path, data)
+            .with_context(|| format!("Failed to write {file_path:?}"))?;
+        println!("{file_path:?}");
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli: Cli = clap::Parser::parse();
+    match cli.command {
+        Command::Info { path } => info(&path),
+        Command::Upgrade { path, output } => upgrade(&path, output.as_deref()),
+        Command::Extract { path, output } => extract(&path, output.as_deref()),
+        Command::Gltf { path, output } => gltf(&path, output.as_deref()),
+        Command::Obj { path, output } => obj(&path, output.as_deref()),
+    }
+}
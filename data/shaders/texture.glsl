@@ -16,9 +16,10 @@ uniform sampler2D u_texture;
 uniform ivec2 u_texture_size;
 uniform vec4 u_color;
 void main() {
-  gl_FragColor = smoothTexture2D(v_uv, u_texture, u_texture_size) * u_color;
-  if (gl_FragColor.a < 0.5) {
+  vec4 color = smoothTexture2D(v_uv, u_texture, u_texture_size);
+  if (color.a < 0.5) {
     discard;
   }
+  gl_FragColor = color * u_color;
 }
 #endif
@@ -0,0 +1,521 @@
+//! CPU-side representation of a .pp file that does not need a GL context
+
+use super::*;
+
+use std::path::PathBuf;
+
+/// RGBA8 pixel data, rows ordered bottom to top like the texture readback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    pub size: vec2<usize>,
+    pub data: Vec<u8>,
+}
+
+impl Pixels {
+    pub fn new(size: vec2<usize>) -> Self {
+        Self {
+            size,
+            data: vec![0; size.x * size.y * 4],
+        }
+    }
+
+    pub fn get(&self, pos: vec2<usize>) -> Rgba<u8> {
+        let i = (pos.y * self.size.x + pos.x) * 4;
+        Rgba::new(
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        )
+    }
+
+    pub fn set(&mut self, pos: vec2<usize>, color: Rgba<u8>) {
+        let i = (pos.y * self.size.x + pos.x) * 4;
+        self.data[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.data.chunks_exact(4).all(|pixel| pixel[3] == 0)
+    }
+
+    /// Smallest rectangle containing every non-transparent pixel
+    pub fn opaque_bounds(&self) -> Option<Aabb2<usize>> {
+        let mut result: Option<Aabb2<usize>> = None;
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                if self.get(vec2(x, y)).a == 0 {
+                    continue;
+                }
+                let pixel = Aabb2::point(vec2(x, y)).extend_positive(vec2(1, 1));
+                result = Some(match result {
+                    Some(bb) => Aabb2 {
+                        min: bb.min.zip(pixel.min).map(|(a, b)| a.min(b)),
+                        max: bb.max.zip(pixel.max).map(|(a, b)| a.max(b)),
+                    },
+                    None => pixel,
+                });
+            }
+        }
+        result
+    }
+
+    /// Copies the given rectangle into new pixels
+    pub fn sub(&self, rect: Aabb2<usize>) -> Self {
+        let mut result = Self::new(rect.size());
+        let row = rect.width() * 4;
+        for y in 0..rect.height() {
+            let from = ((rect.min.y + y) * self.size.x + rect.min.x) * 4;
+            result.data[y * row..(y + 1) * row].copy_from_slice(&self.data[from..from + row]);
+        }
+        result
+    }
+
+    /// Copies other pixels so that their bottom left corner ends up at pos
+    pub fn blit(&mut self, pos: vec2<usize>, other: &Self) {
+        let row = other.size.x * 4;
+        for y in 0..other.size.y {
+            let to = ((pos.y + y) * self.size.x + pos.x) * 4;
+            self.data[to..to + row].copy_from_slice(&other.data[y * row..(y + 1) * row]);
+        }
+    }
+
+    /// Converts to an image with the usual top to bottom row order
+    pub fn to_image(&self) -> image::RgbaImage {
+        let mut image =
+            image::RgbaImage::from_raw(self.size.x as u32, self.size.y as u32, self.data.clone())
+                .expect("pixel data does not match size");
+        image::imageops::flip_vertical_in_place(&mut image);
+        image
+    }
+
+    pub fn from_image(image: &image::RgbaImage) -> Self {
+        let image = image::imageops::flip_vertical(image);
+        Self {
+            size: vec2(image.width() as usize, image.height() as usize),
+            data: image.into_raw(),
+        }
+    }
+
+    pub fn decode_png(png: &[u8]) -> Result<Self, image::ImageError> {
+        let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)?;
+        Ok(Self::from_image(&image.to_rgba8()))
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut png = Vec::new();
+        self.to_image()
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("encoding png to memory can not fail");
+        png
+    }
+}
+
+/// Part of a sparse image, pos is the bottom left corner in plane space
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub pos: vec2<i32>,
+    pub pixels: Pixels,
+}
+
+impl Tile {
+    pub fn bounding_box(&self) -> Aabb2<i32> {
+        Aabb2::point(self.pos).extend_positive(self.pixels.size.map(|x| x as i32))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Image {
+    Load(PathBuf),
+    /// Placed at the plane offset
+    Pixels(Pixels),
+    /// Tiles have their own positions, plane offset is not used
+    Tiles(Vec<Tile>),
+    /// Shares the image of an earlier plane with this index
+    Linked(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    pub image: Option<Image>,
+    pub offset: vec2<i32>,
+    pub parent: Option<usize>,
+    /// Relative to the parent group
+    pub transform: mat4<f32>,
+}
+
+impl Plane {
+    /// Same as [crate::Texture::bounding_box] for the loaded texture
+    pub fn bounding_box(&self) -> Option<Aabb2<i32>> {
+        match &self.image {
+            Some(Image::Pixels(pixels)) => {
+                Some(Aabb2::point(self.offset).extend_positive(pixels.size.map(|x| x as i32)))
+            }
+            Some(Image::Tiles(tiles)) => {
+                tiles.iter().map(Tile::bounding_box).reduce(|a, b| Aabb2 {
+                    min: a.min.zip(b.min).map(|(a, b)| a.min(b)),
+                    max: a.max.zip(b.max).map(|(a, b)| a.max(b)),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Embedded image as rectangles of pixels along with their positions,
+    /// tiles are cropped to their opaque bounds
+    ///
+    /// Touching tiles are merged unless most of the merged rectangle would be empty,
+    /// so strokes far apart do not need an image covering everything in between
+    pub fn flatten(&self) -> Vec<(vec2<i32>, Pixels)> {
+        match &self.image {
+            Some(Image::Pixels(pixels)) => vec![(self.offset, pixels.clone())],
+            Some(Image::Tiles(tiles)) => touching_tiles(tiles)
+                .into_iter()
+                .flat_map(|group| {
+                    let bb = group
+                        .iter()
+                        .map(|tile| tile.bounding_box())
+                        .reduce(|a, b| Aabb2 {
+                            min: a.min.zip(b.min).map(|(a, b)| a.min(b)),
+                            max: a.max.zip(b.max).map(|(a, b)| a.max(b)),
+                        })
+                        .unwrap();
+                    let area = |size: vec2<usize>| size.x * size.y;
+                    let used: usize = group.iter().map(|tile| area(tile.pixels.size)).sum();
+                    if area(bb.size().map(|x| x as usize)) > 2 * used {
+                        return group
+                            .into_iter()
+                            .map(|tile| (tile.pos, tile.pixels.clone()))
+                            .collect();
+                    }
+                    let mut pixels = Pixels::new(bb.size().map(|x| x as usize));
+                    for tile in group {
+                        pixels.blit((tile.pos - bb.min).map(|x| x as usize), &tile.pixels);
+                    }
+                    vec![(bb.min, pixels)]
+                })
+                .filter_map(|(pos, pixels)| {
+                    let opaque = pixels.opaque_bounds()?;
+                    Some((pos + opaque.min.map(|x| x as i32), pixels.sub(opaque)))
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Groups of tiles that touch each other, including diagonally
+fn touching_tiles(tiles: &[Tile]) -> Vec<Vec<&Tile>> {
+    let mut remaining: HashMap<vec2<i32>, &Tile> =
+        tiles.iter().map(|tile| (tile.pos, tile)).collect();
+    let mut groups = Vec::new();
+    for tile in tiles {
+        let Some(tile) = remaining.remove(&tile.pos) else {
+            continue;
+        };
+        let mut group = vec![tile];
+        let mut next = 0;
+        while let Some(&tile) = group.get(next) {
+            next += 1;
+            let size = tile.pixels.size.map(|x| x as i32);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let pos = tile.pos + vec2(dx * size.x, dy * size.y);
+                    if let Some(neighbor) = remaining.remove(&pos) {
+                        group.push(neighbor);
+                    }
+                }
+            }
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub transform: mat4<f32>,
+}
+
+/// Editor camera, angles are in radians
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub pos: vec3<f32>,
+    pub rot: f32,
+    pub attack: f32,
+    pub distance: f32,
+    pub fov: f32,
+}
+
+/// Editor state that is saved along with the planes
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub camera: Camera,
+    pub selected: Option<usize>,
+    pub color: Rgba<f32>,
+    pub palette: Vec<Rgba<f32>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub planes: Vec<Plane>,
+    pub groups: Vec<Group>,
+    pub scene: Option<Scene>,
+}
+
+impl Document {
+    /// Plane that actually stores the image of the given one, following links
+    pub fn image_owner<'a>(&'a self, mut plane: &'a Plane) -> &'a Plane {
+        // Links only go to earlier planes, so anything longer than this is a broken file
+        for _ in 0..self.planes.len() {
+            match plane.image {
+                Some(Image::Linked(target)) if target < self.planes.len() => {
+                    plane = &self.planes[target];
+                }
+                _ => break,
+            }
+        }
+        plane
+    }
+
+    /// Same as [crate::Model::world_transform]
+    pub fn world_transform(&self, plane: &Plane) -> mat4<f32> {
+        let mut transform = plane.transform;
+        let mut parent = plane.parent;
+        while let Some(index) = parent {
+            let group = &self.groups[index];
+            transform = group.transform * transform;
+            parent = group.parent;
+        }
+        transform
+    }
+}
+
+impl Model {
+    pub fn to_document(&self) -> Document {
+        Document {
+            planes: self
+                .planes
+                .iter()
+                .enumerate()
+                .map(|(index, plane)| Plane {
+                    name: plane.name.clone(),
+                    visible: plane.visible,
+                    locked: plane.locked,
+                    opacity: plane.opacity,
+                    // Linked planes point to the first plane with the same texture
+                    image: Some(
+                        match self.planes[..index]
+                            .iter()
+                            .position(|other| other.is_linked_to(plane))
+                        {
+                            Some(target) => Image::Linked(target),
+                            None => Image::Tiles(plane.texture.borrow().to_tiles()),
+                        },
+                    ),
+                    offset: vec2::ZERO,
+                    parent: plane.parent,
+                    transform: plane.transform,
+                })
+                .collect(),
+            groups: self
+                .groups
+                .iter()
+                .map(|group| Group {
+                    name: group.name.clone(),
+                    parent: group.parent,
+                    transform: group.transform,
+                })
+                .collect(),
+            scene: None,
+        }
+    }
+
+    pub async fn from_document(
+        asset_manager: &geng::asset::Manager,
+        document: Document,
+    ) -> Result<Self, file_format::LoadError> {
+        let links: Vec<Option<usize>> = document
+            .planes
+            .iter()
+            .map(|plane| match plane.image {
+                Some(Image::Linked(target)) => Some(target),
+                _ => None,
+            })
+            .collect();
+        let mut planes: Vec<crate::Plane> = stream::iter(document.planes)
+            .then(|plane| async move {
+                let ugli = asset_manager.ugli();
+                let texture = match plane.image {
+                    Some(Image::Load(path)) => {
+                        let texture: ugli::Texture =
+                            asset_manager.load(&path).await.map_err(|source| {
+                                file_format::LoadError::ExternalImage { path, source }
+                            })?;
+                        crate::Texture::from_texture(ugli, &texture, plane.offset)
+                    }
+                    Some(Image::Pixels(pixels)) => {
+                        crate::Texture::from_pixels(ugli, &pixels, plane.offset)
+                    }
+                    Some(Image::Tiles(tiles)) => {
+                        let mut texture = crate::Texture::new(ugli);
+                        for tile in &tiles {
+                            texture.write_pixels(tile.pos, &tile.pixels);
+                        }
+                        texture
+                    }
+                    // Replaced with the shared one below
+                    Some(Image::Linked(_)) | None => crate::Texture::new(ugli),
+                };
+                Ok::<_, file_format::LoadError>(crate::Plane {
+                    name: plane.name,
+                    visible: plane.visible,
+                    locked: plane.locked,
+                    opacity: plane.opacity,
+                    texture: Rc::new(RefCell::new(texture)),
+                    parent: plane.parent,
+                    transform: plane.transform,
+                })
+            })
+            .try_collect()
+            .await?;
+        for (index, target) in links.into_iter().enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+            if target >= index {
+                return Err(file_format::LoadError::BrokenLink {
+                    plane: index,
+                    target,
+                });
+            }
+            planes[index].texture = planes[target].texture.clone();
+        }
+        Ok(Self {
+            ugli: asset_manager.ugli().clone(),
+            planes,
+            raycast_index: default(),
+            groups: document
+                .groups
+                .into_iter()
+                .map(|group| crate::Group {
+                    name: group.name,
+                    parent: group.parent,
+                    transform: group.transform,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every pixel is different, so flips and offsets show up
+    fn gradient(size: vec2<usize>) -> Pixels {
+        let mut pixels = Pixels::new(size);
+        for y in 0..size.y {
+            for x in 0..size.x {
+                pixels.set(vec2(x, y), Rgba::new(x as u8, y as u8, 100, 255 - x as u8));
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let pixels = gradient(vec2(5, 3));
+        let decoded = Pixels::decode_png(&pixels.encode_png()).unwrap();
+        assert_eq!(decoded, pixels);
+    }
+
+    #[test]
+    fn images_go_top_to_bottom() {
+        let pixels = gradient(vec2(2, 3));
+        let image = pixels.to_image();
+        assert_eq!(image.get_pixel(0, 0).0, [0, 2, 100, 255]);
+        assert_eq!(image.get_pixel(1, 2).0, [1, 0, 100, 254]);
+        assert_eq!(Pixels::from_image(&image), pixels);
+    }
+
+    #[test]
+    fn sub_and_blit() {
+        let pixels = gradient(vec2(4, 4));
+        let rect = Aabb2::point(vec2(1, 2)).extend_positive(vec2(2, 1));
+        let sub = pixels.sub(rect);
+        assert_eq!(sub.size, vec2(2, 1));
+        assert_eq!(sub.get(vec2(1, 0)), pixels.get(vec2(2, 2)));
+        let mut target = Pixels::new(vec2(3, 3));
+        target.blit(vec2(1, 1), &sub);
+        assert_eq!(target.get(vec2(2, 1)), pixels.get(vec2(2, 2)));
+        assert_eq!(target.get(vec2(0, 1)).a, 0);
+    }
+
+    #[test]
+    fn opaque_bounds() {
+        let mut pixels = Pixels::new(vec2(5, 5));
+        assert!(pixels.is_transparent());
+        assert_eq!(pixels.opaque_bounds(), None);
+        pixels.set(vec2(1, 3), Rgba::new(0, 0, 0, 1));
+        pixels.set(vec2(3, 2), Rgba::new(0, 0, 0, 255));
+        assert!(!pixels.is_transparent());
+        assert_eq!(
+            pixels.opaque_bounds(),
+            Some(Aabb2::point(vec2(1, 2)).extend_positive(vec2(3, 2)))
+        );
+    }
+
+    fn tile(pos: vec2<i32>) -> Tile {
+        let mut pixels = Pixels::new(vec2(4, 4));
+        pixels.set(vec2(1, 1), Rgba::new(0, 0, 0, 255));
+        Tile { pos, pixels }
+    }
+
+    fn tiled(tiles: Vec<Tile>) -> Plane {
+        Plane {
+            name: String::new(),
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            image: Some(Image::Tiles(tiles)),
+            offset: vec2::ZERO,
+            parent: None,
+            transform: mat4::identity(),
+        }
+    }
+
+    #[test]
+    fn touching_tiles_are_merged() {
+        let plane = tiled(vec![tile(vec2(0, 0)), tile(vec2(4, 0)), tile(vec2(8, 4))]);
+        let parts = plane.flatten();
+        assert_eq!(parts.len(), 1);
+        let (pos, pixels) = &parts[0];
+        assert_eq!(*pos, vec2(1, 1));
+        assert_eq!(pixels.size, vec2(9, 5));
+    }
+
+    #[test]
+    fn far_apart_tiles_are_not_merged() {
+        let plane = tiled(vec![tile(vec2(0, 0)), tile(vec2(-4_000_000, 4_000_000))]);
+        let parts: Vec<vec2<i32>> = plane.flatten().into_iter().map(|(pos, _)| pos).collect();
+        assert_eq!(parts, [vec2(1, 1), vec2(-3_999_999, 4_000_001)]);
+    }
+
+    #[test]
+    fn sparse_touching_tiles_are_not_merged() {
+        // A diagonal line of tiles, the merged rectangle would be mostly empty
+        let plane = tiled((0..10).map(|i| tile(vec2(i * 4, i * 4))).collect());
+        let parts = plane.flatten();
+        assert_eq!(parts.len(), 10);
+        assert!(parts.iter().all(|(_, pixels)| pixels.size == vec2(1, 1)));
+    }
+}
@@ -0,0 +1,325 @@
+use super::*;
+
+use base64::Engine as _;
+
+const NEAREST: u32 = 9728;
+const CLAMP_TO_EDGE: u32 = 33071;
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+struct Builder {
+    json: serde_json::Value,
+    bin: Vec<u8>,
+}
+
+impl Builder {
+    fn push(&mut self, key: &str, value: serde_json::Value) -> usize {
+        let array = self.json[key].as_array_mut().unwrap();
+        array.push(value);
+        array.len() - 1
+    }
+
+    fn buffer_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(data);
+        let mut view = serde_json::json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.push("bufferViews", view)
+    }
+
+    fn accessor(&mut self, data: &[u8], accessor: serde_json::Value, target: u32) -> usize {
+        let view = self.buffer_view(data, Some(target));
+        let mut accessor = accessor;
+        accessor["bufferView"] = view.into();
+        self.push("accessors", accessor)
+    }
+
+    /// Textured quad of a part, with its own image and material
+    fn primitive(&mut self, part: &Part, name: String) -> serde_json::Value {
+        let quad = quad(part.bounding_box());
+
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for (pos, uv) in quad {
+            for x in [pos.x, pos.y, 0.0] {
+                positions.extend_from_slice(&x.to_le_bytes());
+            }
+            for x in [uv.x, uv.y] {
+                uvs.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        let indices: Vec<u8> = [0u16, 1, 2, 0, 2, 3]
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+
+        let position = self.accessor(
+            &positions,
+            serde_json::json!({
+                "componentType": FLOAT,
+                "count": 4,
+                "type": "VEC3",
+                "min": [quad[0].0.x, quad[0].0.y, 0.0],
+                "max": [quad[2].0.x, quad[2].0.y, 0.0],
+            }),
+            ARRAY_BUFFER,
+        );
+        let uv = self.accessor(
+            &uvs,
+            serde_json::json!({
+                "componentType": FLOAT,
+                "count": 4,
+                "type": "VEC2",
+            }),
+            ARRAY_BUFFER,
+        );
+        let indices = self.accessor(
+            &indices,
+            serde_json::json!({
+                "componentType": UNSIGNED_SHORT,
+                "count": 6,
+                "type": "SCALAR",
+            }),
+            ELEMENT_ARRAY_BUFFER,
+        );
+
+        let image_view = self.buffer_view(&part.pixels.encode_png(), None);
+        let image_index = self.push(
+            "images",
+            serde_json::json!({ "bufferView": image_view, "mimeType": "image/png" }),
+        );
+        let texture = self.push(
+            "textures",
+            serde_json::json!({ "sampler": 0, "source": image_index }),
+        );
+        let material = self.push(
+            "materials",
+            serde_json::json!({
+                "name": name,
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": texture },
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.5,
+                "doubleSided": true,
+                "extensions": { "KHR_materials_unlit": {} },
+            }),
+        );
+        serde_json::json!({
+            "attributes": { "POSITION": position, "TEXCOORD_0": uv },
+            "indices": indices,
+            "material": material,
+        })
+    }
+}
+
+fn matrix(m: mat4<f32>) -> Vec<f32> {
+    let mut result = Vec::with_capacity(16);
+    for col in 0..4 {
+        for row in 0..4 {
+            result.push(m[(row, col)]);
+        }
+    }
+    result
+}
+
+fn build(document: &Document) -> Builder {
+    let mut builder = Builder {
+        json: serde_json::json!({
+            "asset": { "version": "2.0", "generator": "PogPaint" },
+            "extensionsUsed": ["KHR_materials_unlit"],
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            // PogPaint is z-up while gltf is y-up
+            "nodes": [{
+                "name": "PogPaint",
+                "matrix": matrix(mat4::rotate_x(Angle::from_degrees(-90.0))),
+                "children": [],
+            }],
+            "meshes": [],
+            "materials": [],
+            "textures": [],
+            "images": [],
+            "samplers": [{
+                "magFilter": NEAREST,
+                "minFilter": NEAREST,
+                "wrapS": CLAMP_TO_EDGE,
+                "wrapT": CLAMP_TO_EDGE,
+            }],
+            "accessors": [],
+            "bufferViews": [],
+        }),
+        bin: Vec::new(),
+    };
+    // Groups become nodes so that the hierarchy is kept
+    let group_nodes: Vec<usize> = document
+        .groups
+        .iter()
+        .enumerate()
+        .map(|(index, group)| {
+            builder.push(
+                "nodes",
+                serde_json::json!({
+                    "name": if group.name.is_empty() {
+                        format!("group {index}")
+                    } else {
+                        group.name.clone()
+                    },
+                    "matrix": matrix(group.transform),
+                    "children": [],
+                }),
+            )
+        })
+        .collect();
+    let parent_node = |parent: Option<usize>| parent.map_or(0, |index| group_nodes[index]);
+    for (group, &node) in document.groups.iter().zip(&group_nodes) {
+        builder.json["nodes"][parent_node(group.parent)]["children"]
+            .as_array_mut()
+            .unwrap()
+            .push(node.into());
+    }
+    // Linked planes share the image, so they also share the mesh and material
+    let mut meshes = HashMap::<usize, usize>::new();
+    for textured in textured_planes(document) {
+        let TexturedPlane {
+            index,
+            plane,
+            image,
+            ref parts,
+        } = textured;
+        let mesh = if let Some(&mesh) = meshes.get(&image) {
+            mesh
+        } else {
+            let primitives: Vec<serde_json::Value> = parts
+                .iter()
+                .enumerate()
+                .map(|(part_index, part)| {
+                    let name = format!("plane {image}{}", part_suffix(part_index, parts.len()));
+                    builder.primitive(part, name)
+                })
+                .collect();
+            let mesh = builder.push(
+                "meshes",
+                serde_json::json!({
+                    "name": format!("plane {image}"),
+                    "primitives": primitives,
+                }),
+            );
+            meshes.insert(image, mesh);
+            mesh
+        };
+        let node = builder.push(
+            "nodes",
+            serde_json::json!({
+                "name": format!("plane {index}"),
+                "mesh": mesh,
+                "matrix": matrix(plane.transform),
+            }),
+        );
+        builder.json["nodes"][parent_node(plane.parent)]["children"]
+            .as_array_mut()
+            .unwrap()
+            .push(node.into());
+    }
+    while builder.bin.len() % 4 != 0 {
+        builder.bin.push(0);
+    }
+    // The spec does not allow empty arrays, which is what an empty document leaves
+    for node in builder.json["nodes"].as_array_mut().unwrap() {
+        remove_empty_arrays(node);
+    }
+    remove_empty_arrays(&mut builder.json);
+    builder
+}
+
+fn remove_empty_arrays(value: &mut serde_json::Value) {
+    value
+        .as_object_mut()
+        .unwrap()
+        .retain(|_, value| value.as_array().map_or(true, |array| !array.is_empty()));
+}
+
+/// Export as a single .glb file
+pub fn to_glb(document: &Document) -> Vec<u8> {
+    let mut builder = build(document);
+    if !builder.bin.is_empty() {
+        builder.json["buffers"] = serde_json::json!([{ "byteLength": builder.bin.len() }]);
+    }
+    let mut json = serde_json::to_vec(&builder.json).unwrap();
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    let mut result = Vec::new();
+    // The binary chunk is optional and must be left out when there is no data
+    let bin_chunk_length = if builder.bin.is_empty() {
+        0
+    } else {
+        8 + builder.bin.len()
+    };
+    let length = 12 + 8 + json.len() + bin_chunk_length;
+    result.extend_from_slice(b"glTF");
+    result.extend_from_slice(&2u32.to_le_bytes());
+    result.extend_from_slice(&(length as u32).to_le_bytes());
+    result.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    result.extend_from_slice(b"JSON");
+    result.extend_from_slice(&json);
+    if !builder.bin.is_empty() {
+        result.extend_from_slice(&(builder.bin.len() as u32).to_le_bytes());
+        result.extend_from_slice(b"BIN\0");
+        result.extend_from_slice(&builder.bin);
+    }
+    result
+}
+
+/// Export as a .gltf file with the binary data embedded as a data uri
+pub fn to_gltf(document: &Document) -> Vec<u8> {
+    let mut builder = build(document);
+    if !builder.bin.is_empty() {
+        builder.json["buffers"] = serde_json::json!([{
+            "byteLength": builder.bin.len(),
+            "uri": format!(
+                "data:application/octet-stream;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(&builder.bin),
+            ),
+        }]);
+    }
+    serde_json::to_vec_pretty(&builder.json).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_document_has_no_buffers() {
+        let glb = to_glb(&Document::default());
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        assert_eq!(glb.len(), 12 + 8 + json_length);
+        let json: serde_json::Value = serde_json::from_slice(&glb[20..]).unwrap();
+        assert!(json.get("buffers").is_none());
+        assert!(json.get("meshes").is_none());
+        assert!(json["nodes"][0].get("children").is_none());
+
+        let gltf: serde_json::Value =
+            serde_json::from_slice(&to_gltf(&Document::default())).unwrap();
+        assert!(gltf.get("buffers").is_none());
+    }
+}
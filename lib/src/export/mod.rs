@@ -0,0 +1,86 @@
+use super::*;
+
+pub mod gltf;
+pub mod obj;
+
+/// Corners of the textured quad in plane space, the same one [crate::Texture] is drawn with
+fn quad(bounding_box: Aabb2<i32>) -> [(vec2<f32>, vec2<f32>); 4] {
+    let bb = bounding_box.map(|x| x as f32);
+    // png rows go top to bottom so uv y is flipped
+    [
+        (vec2(bb.min.x, bb.min.y), vec2(0.0, 1.0)),
+        (vec2(bb.max.x, bb.min.y), vec2(1.0, 1.0)),
+        (vec2(bb.max.x, bb.max.y), vec2(1.0, 0.0)),
+        (vec2(bb.min.x, bb.max.y), vec2(0.0, 0.0)),
+    ]
+}
+
+/// Rectangle of pixels of an image, see [document::Plane::flatten]
+struct Part {
+    offset: vec2<i32>,
+    pixels: document::Pixels,
+}
+
+impl Part {
+    fn bounding_box(&self) -> Aabb2<i32> {
+        Aabb2::point(self.offset).extend_positive(self.pixels.size.map(|x| x as i32))
+    }
+}
+
+struct TexturedPlane<'a> {
+    index: usize,
+    plane: &'a document::Plane,
+    /// Index of the plane that owns the image, linked planes share it
+    image: usize,
+    parts: Rc<Vec<Part>>,
+}
+
+/// File name suffix of a part, only added when the image has several
+fn part_suffix(part: usize, parts: usize) -> String {
+    if parts == 1 {
+        String::new()
+    } else {
+        format!("-{part}")
+    }
+}
+
+/// Visible planes that have embedded pixels, flattened into parts
+///
+/// Every shared image is only flattened once
+fn textured_planes(document: &Document) -> impl Iterator<Item = TexturedPlane> {
+    let mut flattened = HashMap::<usize, Rc<Vec<Part>>>::new();
+    document
+        .planes
+        .iter()
+        .enumerate()
+        .filter(|(_index, plane)| plane.visible)
+        .filter_map(move |(index, plane)| {
+            let owner = document.image_owner(plane);
+            let image = document
+                .planes
+                .iter()
+                .position(|plane| std::ptr::eq(plane, owner))
+                .unwrap();
+            let parts = flattened
+                .entry(image)
+                .or_insert_with(|| {
+                    Rc::new(
+                        owner
+                            .flatten()
+                            .into_iter()
+                            .map(|(offset, pixels)| Part { offset, pixels })
+                            .collect(),
+                    )
+                })
+                .clone();
+            if parts.is_empty() {
+                return None;
+            }
+            Some(TexturedPlane {
+                index,
+                plane,
+                image,
+                parts,
+            })
+        })
+}
@@ -0,0 +1,77 @@
+use super::*;
+
+use std::fmt::Write as _;
+
+/// Export as wavefront obj, returns a list of `(file name, contents)`
+///
+/// Produces `{name}.obj`, `{name}.mtl` and a `{name}-{index}.png` for every image,
+/// planes linked to the same image share its material.
+/// Images that are split into parts get a `-{part}` suffix and a quad per part.
+/// Vertices are in world space using the same z-up coordinates as the editor.
+pub fn export(document: &Document, name: &str) -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    let mut obj = String::new();
+    let mut mtl = String::new();
+    writeln!(obj, "mtllib {name}.mtl").unwrap();
+    let mut materials = HashSet::new();
+    let mut quads = 0;
+    for (plane_index, textured) in textured_planes(document).enumerate() {
+        let TexturedPlane {
+            index,
+            plane,
+            image,
+            ref parts,
+        } = textured;
+        let add_materials = materials.insert(image);
+
+        writeln!(obj, "g plane_{index}").unwrap();
+        let transform = document.world_transform(plane);
+        // Normals go through the inverse transpose to stay perpendicular under non-uniform scale
+        let normal = (transform.inverse().transpose() * vec4(0.0, 0.0, 1.0, 0.0))
+            .xyz()
+            .normalize_or_zero();
+        writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z).unwrap();
+        for (part_index, part) in parts.iter().enumerate() {
+            let suffix = part_suffix(part_index, parts.len());
+            let material = format!("plane_{image}{suffix}");
+            let png = format!("{name}-{image}{suffix}.png");
+
+            writeln!(obj, "usemtl {material}").unwrap();
+            let quad = quad(part.bounding_box());
+            for (pos, _uv) in quad {
+                let pos = (transform * pos.extend(0.0).extend(1.0)).into_3d();
+                writeln!(obj, "v {} {} {}", pos.x, pos.y, pos.z).unwrap();
+            }
+            for (_pos, uv) in quad {
+                // obj texture coordinates start at the bottom
+                writeln!(obj, "vt {} {}", uv.x, 1.0 - uv.y).unwrap();
+            }
+            // indices are 1-based, every quad adds 4 vertices and every plane 1 normal
+            write!(obj, "f").unwrap();
+            for i in 1..=4 {
+                let v = quads * 4 + i;
+                write!(obj, " {v}/{v}/{}", plane_index + 1).unwrap();
+            }
+            writeln!(obj).unwrap();
+            quads += 1;
+
+            if !add_materials {
+                continue;
+            }
+            writeln!(mtl, "newmtl {material}").unwrap();
+            writeln!(mtl, "Ka 0 0 0").unwrap();
+            writeln!(mtl, "Kd 1 1 1").unwrap();
+            writeln!(mtl, "Ks 0 0 0").unwrap();
+            writeln!(mtl, "d 1").unwrap();
+            writeln!(mtl, "illum 0").unwrap();
+            writeln!(mtl, "map_Kd {png}").unwrap();
+            writeln!(mtl, "map_d {png}").unwrap();
+            writeln!(mtl).unwrap();
+
+            files.push((png, part.pixels.encode_png()));
+        }
+    }
+    files.insert(0, (format!("{name}.mtl"), mtl.into_bytes()));
+    files.insert(0, (format!("{name}.obj"), obj.into_bytes()));
+    files
+}
@@ -78,111 +78,728 @@ pub mod versions {
             }
         }
     }
+
+    pub mod v2 {
+        use super::*;
+        pub const VERSION: u8 = 2;
+
+        pub use v1::Image;
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Plane {
+            pub name: String,
+            pub visible: bool,
+            pub locked: bool,
+            pub opacity: f32,
+            pub image: Option<Image>,
+            pub offset: vec2<i32>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+        }
+
+        impl From<v1::Plane> for Plane {
+            fn from(old: v1::Plane) -> Self {
+                Self {
+                    name: String::new(),
+                    visible: true,
+                    locked: false,
+                    opacity: 1.0,
+                    image: old.image,
+                    offset: old.offset,
+                    transform: old.transform,
+                }
+            }
+        }
+
+        impl From<v1::Pp> for Pp {
+            fn from(old: v1::Pp) -> Self {
+                Self {
+                    planes: old.planes.into_iter().map(Into::into).collect(),
+                }
+            }
+        }
+    }
+
+    pub mod v3 {
+        use super::*;
+        pub const VERSION: u8 = 3;
+
+        pub use v2::{Image, Plane};
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Camera {
+            pub pos: vec3<f32>,
+            pub rot: f32,
+            pub attack: f32,
+            pub distance: f32,
+            pub fov: f32,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Scene {
+            pub camera: Camera,
+            pub selected: Option<usize>,
+            pub color: Rgba<f32>,
+            pub palette: Vec<Rgba<f32>>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+            pub scene: Option<Scene>,
+        }
+
+        impl From<v2::Pp> for Pp {
+            fn from(old: v2::Pp) -> Self {
+                Self {
+                    planes: old.planes,
+                    scene: None,
+                }
+            }
+        }
+    }
+
+    pub mod v4 {
+        use super::*;
+        pub const VERSION: u8 = 4;
+
+        pub use v3::{Camera, Scene};
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Image {
+            Load(PathBuf),
+            Embed { size: vec2<usize>, data: Vec<u8> },
+            Png(Vec<u8>),
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Plane {
+            pub name: String,
+            pub visible: bool,
+            pub locked: bool,
+            pub opacity: f32,
+            pub image: Option<Image>,
+            pub offset: vec2<i32>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+            pub scene: Option<Scene>,
+        }
+
+        impl From<v3::Image> for Image {
+            fn from(old: v3::Image) -> Self {
+                match old {
+                    v3::Image::Load(path) => Self::Load(path),
+                    v3::Image::Embed { size, data } => Self::Embed { size, data },
+                }
+            }
+        }
+
+        impl From<v3::Plane> for Plane {
+            fn from(old: v3::Plane) -> Self {
+                Self {
+                    name: old.name,
+                    visible: old.visible,
+                    locked: old.locked,
+                    opacity: old.opacity,
+                    image: old.image.map(Into::into),
+                    offset: old.offset,
+                    transform: old.transform,
+                }
+            }
+        }
+
+        impl From<v3::Pp> for Pp {
+            fn from(old: v3::Pp) -> Self {
+                Self {
+                    planes: old.planes.into_iter().map(Into::into).collect(),
+                    scene: old.scene,
+                }
+            }
+        }
+    }
+
+    pub mod v5 {
+        use super::*;
+        pub const VERSION: u8 = 5;
+
+        pub use v4::{Camera, Scene};
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Tile {
+            pub pos: vec2<i32>,
+            pub png: Vec<u8>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Image {
+            Load(PathBuf),
+            Embed { size: vec2<usize>, data: Vec<u8> },
+            Png(Vec<u8>),
+            Tiles(Vec<Tile>),
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Plane {
+            pub name: String,
+            pub visible: bool,
+            pub locked: bool,
+            pub opacity: f32,
+            pub image: Option<Image>,
+            pub offset: vec2<i32>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+            pub scene: Option<Scene>,
+        }
+
+        impl From<v4::Image> for Image {
+            fn from(old: v4::Image) -> Self {
+                match old {
+                    v4::Image::Load(path) => Self::Load(path),
+                    v4::Image::Embed { size, data } => Self::Embed { size, data },
+                    v4::Image::Png(png) => Self::Png(png),
+                }
+            }
+        }
+
+        impl From<v4::Plane> for Plane {
+            fn from(old: v4::Plane) -> Self {
+                Self {
+                    name: old.name,
+                    visible: old.visible,
+                    locked: old.locked,
+                    opacity: old.opacity,
+                    image: old.image.map(Into::into),
+                    offset: old.offset,
+                    transform: old.transform,
+                }
+            }
+        }
+
+        impl From<v4::Pp> for Pp {
+            fn from(old: v4::Pp) -> Self {
+                Self {
+                    planes: old.planes.into_iter().map(Into::into).collect(),
+                    scene: old.scene,
+                }
+            }
+        }
+    }
+
+    pub mod v6 {
+        use super::*;
+        pub const VERSION: u8 = 6;
+
+        pub use v5::{Camera, Image, Scene, Tile};
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Plane {
+            pub name: String,
+            pub visible: bool,
+            pub locked: bool,
+            pub opacity: f32,
+            pub image: Option<Image>,
+            pub offset: vec2<i32>,
+            pub parent: Option<usize>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Group {
+            pub name: String,
+            pub parent: Option<usize>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+            pub groups: Vec<Group>,
+            pub scene: Option<Scene>,
+        }
+
+        impl From<v5::Plane> for Plane {
+            fn from(old: v5::Plane) -> Self {
+                Self {
+                    name: old.name,
+                    visible: old.visible,
+                    locked: old.locked,
+                    opacity: old.opacity,
+                    image: old.image,
+                    offset: old.offset,
+                    parent: None,
+                    transform: old.transform,
+                }
+            }
+        }
+
+        impl From<v5::Pp> for Pp {
+            fn from(old: v5::Pp) -> Self {
+                Self {
+                    planes: old.planes.into_iter().map(Into::into).collect(),
+                    groups: vec![],
+                    scene: old.scene,
+                }
+            }
+        }
+    }
+
+    pub mod v7 {
+        use super::*;
+        pub const VERSION: u8 = 7;
+
+        pub use v6::{Camera, Group, Scene, Tile};
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Image {
+            Load(PathBuf),
+            Embed {
+                size: vec2<usize>,
+                data: Vec<u8>,
+            },
+            Png(Vec<u8>),
+            Tiles(Vec<Tile>),
+            /// Index of an earlier plane to share the image with
+            Linked(usize),
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Plane {
+            pub name: String,
+            pub visible: bool,
+            pub locked: bool,
+            pub opacity: f32,
+            pub image: Option<Image>,
+            pub offset: vec2<i32>,
+            pub parent: Option<usize>,
+            pub transform: mat4<f32>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Pp {
+            pub planes: Vec<Plane>,
+            pub groups: Vec<Group>,
+            pub scene: Option<Scene>,
+        }
+
+        impl From<v6::Image> for Image {
+            fn from(old: v6::Image) -> Self {
+                match old {
+                    v6::Image::Load(path) => Self::Load(path),
+                    v6::Image::Embed { size, data } => Self::Embed { size, data },
+                    v6::Image::Png(png) => Self::Png(png),
+                    v6::Image::Tiles(tiles) => Self::Tiles(tiles),
+                }
+            }
+        }
+
+        impl From<v6::Plane> for Plane {
+            fn from(old: v6::Plane) -> Self {
+                Self {
+                    name: old.name,
+                    visible: old.visible,
+                    locked: old.locked,
+                    opacity: old.opacity,
+                    image: old.image.map(Into::into),
+                    offset: old.offset,
+                    parent: old.parent,
+                    transform: old.transform,
+                }
+            }
+        }
+
+        impl From<v6::Pp> for Pp {
+            fn from(old: v6::Pp) -> Self {
+                Self {
+                    planes: old.planes.into_iter().map(Into::into).collect(),
+                    groups: old.groups,
+                    scene: old.scene,
+                }
+            }
+        }
+    }
 }
 
-use versions::v1 as current_version;
+use versions::v7 as current_version;
 
-use current_version::{Image, Plane, Pp};
+use current_version::{Camera, Group, Image, Plane, Pp, Scene, Tile};
+
+pub const VERSION: u8 = current_version::VERSION;
 
 const HEADER: &str = "VersionedPogPaint";
 
-impl Model {
-    pub fn save(&self, mut writer: impl std::io::Write) -> std::io::Result<()> {
-        let pp = Pp {
-            planes: self
-                .planes
-                .iter()
-                .map(|plane| Plane {
-                    image: plane.texture.texture.as_ref().map(|texture| Image::Embed {
-                        size: texture.size(),
-                        data: {
-                            let framebuffer = ugli::FramebufferRead::new_color(
-                                &self.ugli,
-                                ugli::ColorAttachmentRead::Texture(texture),
-                            );
-                            framebuffer.read_color().data().to_vec()
-                        },
-                    }),
-                    offset: plane.texture.offset,
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a PogPaint file")]
+    BadHeader,
+    #[error("unsupported file format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("failed to decompress: {0}")]
+    Decompression(#[source] std::io::Error),
+    #[error("failed to decode: {0}")]
+    Decoding(#[from] bincode::Error),
+    #[error("failed to decode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("failed to load image {path:?}: {source}")]
+    ExternalImage {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("plane {plane} is linked to plane {target} which does not come before it")]
+    BrokenLink { plane: usize, target: usize },
+    #[error("plane {plane} is in group {group} which does not exist")]
+    MissingPlaneParent { plane: usize, group: usize },
+    #[error("group {group} is in group {parent} which does not exist")]
+    MissingGroupParent { group: usize, parent: usize },
+    #[error("group {group} is nested inside of itself")]
+    GroupCycle { group: usize },
+    #[error("embedded image of size {size:?} has {len} bytes of pixel data")]
+    BadEmbedSize { size: vec2<usize>, len: usize },
+    #[error("tile at {pos:?} has size {size:?} which is not the tile size")]
+    BadTileSize { pos: vec2<i32>, size: vec2<usize> },
+}
+
+/// Maps the items on all available threads, images are encoded and decoded independently
+fn par_map<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        items.into_iter().map(f).collect()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = items.len().div_ceil(threads).max(1);
+        let mut items = items.into_iter();
+        let chunks: Vec<Vec<T>> = std::iter::from_fn(|| {
+            let chunk: Vec<T> = items.by_ref().take(chunk_size).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        })
+        .collect();
+        let f = &f;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| scope.spawn(move || chunk.into_iter().map(f).collect::<Vec<R>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+}
+
+impl TryFrom<Image> for document::Image {
+    type Error = LoadError;
+    fn try_from(image: Image) -> Result<Self, LoadError> {
+        Ok(match image {
+            Image::Load(path) => Self::Load(path),
+            Image::Embed { size, data } => {
+                if size.x.checked_mul(size.y).and_then(|n| n.checked_mul(4)) != Some(data.len()) {
+                    return Err(LoadError::BadEmbedSize {
+                        size,
+                        len: data.len(),
+                    });
+                }
+                Self::Pixels(document::Pixels { size, data })
+            }
+            Image::Png(png) => Self::Pixels(document::Pixels::decode_png(&png)?),
+            Image::Tiles(tiles) => Self::Tiles(
+                tiles
+                    .into_iter()
+                    .map(|tile| {
+                        let pixels = document::Pixels::decode_png(&tile.png)?;
+                        // Tiles are saved exactly as the texture keeps them
+                        if pixels.size != vec2::splat(crate::Texture::TILE_SIZE) {
+                            return Err(LoadError::BadTileSize {
+                                pos: tile.pos,
+                                size: pixels.size,
+                            });
+                        }
+                        Ok::<_, LoadError>(document::Tile {
+                            pos: tile.pos,
+                            pixels,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            Image::Linked(target) => Self::Linked(target),
+        })
+    }
+}
+
+impl From<document::Image> for Image {
+    fn from(image: document::Image) -> Self {
+        match image {
+            document::Image::Load(path) => Self::Load(path),
+            document::Image::Pixels(pixels) => Self::Png(pixels.encode_png()),
+            document::Image::Tiles(tiles) => Self::Tiles(
+                tiles
+                    .into_iter()
+                    .map(|tile| Tile {
+                        pos: tile.pos,
+                        png: tile.pixels.encode_png(),
+                    })
+                    .collect(),
+            ),
+            document::Image::Linked(target) => Self::Linked(target),
+        }
+    }
+}
+
+impl From<Scene> for document::Scene {
+    fn from(scene: Scene) -> Self {
+        Self {
+            camera: document::Camera {
+                pos: scene.camera.pos,
+                rot: scene.camera.rot,
+                attack: scene.camera.attack,
+                distance: scene.camera.distance,
+                fov: scene.camera.fov,
+            },
+            selected: scene.selected,
+            color: scene.color,
+            palette: scene.palette,
+        }
+    }
+}
+
+impl From<document::Scene> for Scene {
+    fn from(scene: document::Scene) -> Self {
+        Self {
+            camera: Camera {
+                pos: scene.camera.pos,
+                rot: scene.camera.rot,
+                attack: scene.camera.attack,
+                distance: scene.camera.distance,
+                fov: scene.camera.fov,
+            },
+            selected: scene.selected,
+            color: scene.color,
+            palette: scene.palette,
+        }
+    }
+}
+
+/// Every parent must exist and following them must end at the root,
+/// otherwise computing world transforms never finishes
+fn check_parents(pp: &Pp) -> Result<(), LoadError> {
+    let groups = &pp.groups;
+    for (plane, parent) in pp.planes.iter().enumerate() {
+        if let Some(group) = parent.parent.filter(|&group| group >= groups.len()) {
+            return Err(LoadError::MissingPlaneParent { plane, group });
+        }
+    }
+    for (group, parent) in groups.iter().enumerate() {
+        if let Some(parent) = parent.parent.filter(|&parent| parent >= groups.len()) {
+            return Err(LoadError::MissingGroupParent { group, parent });
+        }
+    }
+    for group in 0..groups.len() {
+        // A chain longer than the number of groups has to go around a cycle,
+        // which is found when starting from one of the groups in it
+        let mut current = groups[group].parent;
+        for _ in 0..groups.len() {
+            match current {
+                None => break,
+                Some(parent) if parent == group => return Err(LoadError::GroupCycle { group }),
+                Some(parent) => current = groups[parent].parent,
+            }
+        }
+    }
+    Ok(())
+}
+
+impl TryFrom<Pp> for Document {
+    type Error = LoadError;
+    fn try_from(pp: Pp) -> Result<Self, LoadError> {
+        check_parents(&pp)?;
+        Ok(Self {
+            planes: par_map(pp.planes, |plane| {
+                Ok::<_, LoadError>(document::Plane {
+                    name: plane.name,
+                    visible: plane.visible,
+                    locked: plane.locked,
+                    opacity: plane.opacity,
+                    image: plane.image.map(TryInto::try_into).transpose()?,
+                    offset: plane.offset,
+                    parent: plane.parent,
                     transform: plane.transform,
                 })
+            })
+            .into_iter()
+            .collect::<Result<_, _>>()?,
+            groups: pp
+                .groups
+                .into_iter()
+                .map(|group| document::Group {
+                    name: group.name,
+                    parent: group.parent,
+                    transform: group.transform,
+                })
                 .collect(),
-        };
+            scene: pp.scene.map(Into::into),
+        })
+    }
+}
+
+impl From<Document> for Pp {
+    fn from(document: Document) -> Self {
+        Self {
+            planes: par_map(document.planes, |plane| Plane {
+                name: plane.name,
+                visible: plane.visible,
+                locked: plane.locked,
+                opacity: plane.opacity,
+                image: plane.image.map(Into::into),
+                offset: plane.offset,
+                parent: plane.parent,
+                transform: plane.transform,
+            }),
+            groups: document
+                .groups
+                .into_iter()
+                .map(|group| Group {
+                    name: group.name,
+                    parent: group.parent,
+                    transform: group.transform,
+                })
+                .collect(),
+            scene: document.scene.map(Into::into),
+        }
+    }
+}
+
+impl Document {
+    pub fn save(self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+        let pp = Pp::from(self);
         writer.write_all(HEADER.as_bytes())?;
         writer.write_all(&[current_version::VERSION])?;
-        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::best());
-        encoder.write_all(&bincode::serialize(&pp).unwrap())?;
-        encoder.finish()?;
+        // images are already compressed as png so the rest is not worth gzipping
+        bincode::serialize_into(writer, &pp).map_err(|e| match *e {
+            bincode::ErrorKind::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e),
+        })?;
         Ok(())
     }
 
-    pub async fn load(
-        asset_manager: &geng::asset::Manager,
-        reader: impl AsyncBufRead,
-    ) -> std::io::Result<Self> {
-        let mut buf = Vec::new();
-        let mut reader = std::pin::pin!(reader);
+    pub fn load(reader: impl std::io::Read) -> Result<Self, LoadError> {
+        Ok(Self::load_versioned(reader)?.1)
+    }
+
+    /// Same as [Document::load] but also returns the version the file was saved with
+    pub fn load_versioned(mut reader: impl std::io::Read) -> Result<(u8, Self), LoadError> {
         let version = {
-            let mut first_byte = vec![0];
-            reader.read_exact(&mut first_byte).await?;
+            let mut first_byte = [0];
+            reader.read_exact(&mut first_byte)?;
             let first_byte = first_byte[0];
             if first_byte == versions::v0::HEADER.as_bytes()[0] {
                 let mut header = vec![0; versions::v0::HEADER.len()];
                 header[0] = first_byte;
-                reader.read_exact(&mut header[1..]).await?;
-                assert_eq!(header, versions::v0::HEADER.as_bytes());
+                reader.read_exact(&mut header[1..])?;
+                if header != versions::v0::HEADER.as_bytes() {
+                    return Err(LoadError::BadHeader);
+                }
                 0
             } else {
                 let mut header = vec![0; HEADER.len()];
                 header[0] = first_byte;
-                reader.read_exact(&mut header[1..]).await?;
-                assert_eq!(header, HEADER.as_bytes());
-                let mut version_byte = vec![0];
-                reader.read_exact(&mut version_byte).await?;
+                reader.read_exact(&mut header[1..])?;
+                if header != HEADER.as_bytes() {
+                    return Err(LoadError::BadHeader);
+                }
+                let mut version_byte = [0];
+                reader.read_exact(&mut version_byte)?;
                 version_byte[0]
             }
         };
-        println!("{version}");
-        reader.read_to_end(&mut buf).await.unwrap();
-        let mut decoder = flate2::read::GzDecoder::new(buf.as_slice());
         let mut buf = Vec::new();
-        decoder.read_to_end(&mut buf).unwrap();
+        if version < versions::v4::VERSION {
+            let mut decoder = flate2::read::GzDecoder::new(reader);
+            decoder
+                .read_to_end(&mut buf)
+                .map_err(LoadError::Decompression)?;
+        } else {
+            reader.read_to_end(&mut buf)?;
+        }
         let pp: current_version::Pp = match version {
             0 => {
-                let v0: versions::v0::Pp = bincode::deserialize(&buf).unwrap();
-                v0.into()
+                let v0: versions::v0::Pp = bincode::deserialize(&buf)?;
+                let v1 = versions::v1::Pp::from(v0);
+                let v2 = versions::v2::Pp::from(v1);
+                let v3 = versions::v3::Pp::from(v2);
+                let v4 = versions::v4::Pp::from(v3);
+                let v5 = versions::v5::Pp::from(v4);
+                versions::v6::Pp::from(v5).into()
             }
-            versions::v1::VERSION => bincode::deserialize(&buf).unwrap(),
-            _ => todo!("unknown version {version}"),
+            versions::v1::VERSION => {
+                let v1: versions::v1::Pp = bincode::deserialize(&buf)?;
+                let v2 = versions::v2::Pp::from(v1);
+                let v3 = versions::v3::Pp::from(v2);
+                let v4 = versions::v4::Pp::from(v3);
+                let v5 = versions::v5::Pp::from(v4);
+                versions::v6::Pp::from(v5).into()
+            }
+            versions::v2::VERSION => {
+                let v2: versions::v2::Pp = bincode::deserialize(&buf)?;
+                let v3 = versions::v3::Pp::from(v2);
+                let v4 = versions::v4::Pp::from(v3);
+                let v5 = versions::v5::Pp::from(v4);
+                versions::v6::Pp::from(v5).into()
+            }
+            versions::v3::VERSION => {
+                let v3: versions::v3::Pp = bincode::deserialize(&buf)?;
+                let v4 = versions::v4::Pp::from(v3);
+                let v5 = versions::v5::Pp::from(v4);
+                versions::v6::Pp::from(v5).into()
+            }
+            versions::v4::VERSION => {
+                let v4: versions::v4::Pp = bincode::deserialize(&buf)?;
+                let v5 = versions::v5::Pp::from(v4);
+                versions::v6::Pp::from(v5).into()
+            }
+            versions::v5::VERSION => {
+                let v5: versions::v5::Pp = bincode::deserialize(&buf)?;
+                versions::v6::Pp::from(v5).into()
+            }
+            versions::v6::VERSION => {
+                let v6: versions::v6::Pp = bincode::deserialize(&buf)?;
+                v6.into()
+            }
+            versions::v7::VERSION => bincode::deserialize(&buf)?,
+            _ => return Err(LoadError::UnsupportedVersion(version)),
         };
+        Ok((version, pp.try_into()?))
+    }
+}
 
-        Ok(Self {
-            ugli: asset_manager.ugli().clone(),
-            planes: stream::iter(pp.planes.into_iter())
-                .then(|plane| async move {
-                    let mut texture = match plane.image {
-                        Some(image) => Some(match image {
-                            Image::Load(path) => asset_manager.load(path).await.unwrap(),
-                            Image::Embed { size, data } => {
-                                let mut texture =
-                                    ugli::Texture::new_uninitialized(asset_manager.ugli(), size);
-                                texture.sub_image(vec2::ZERO, size, &data);
-                                texture
-                            }
-                        }),
-                        None => None,
-                    };
-                    if let Some(texture) = &mut texture {
-                        texture.set_filter(ugli::Filter::Nearest);
-                    }
-                    crate::Plane {
-                        texture: crate::Texture::from(asset_manager.ugli(), texture, plane.offset),
-                        transform: plane.transform,
-                    }
-                })
-                .collect()
-                .await,
-        })
+impl Model {
+    pub fn save(&self, writer: impl std::io::Write) -> std::io::Result<()> {
+        self.to_document().save(writer)
+    }
+
+    pub async fn load(
+        asset_manager: &geng::asset::Manager,
+        reader: impl AsyncBufRead,
+    ) -> Result<Self, LoadError> {
+        let mut buf = Vec::new();
+        std::pin::pin!(reader).read_to_end(&mut buf).await?;
+        let document = Document::load(buf.as_slice())?;
+        Self::from_document(asset_manager, document).await
     }
 }
 
@@ -205,3 +822,372 @@ impl geng::asset::Load for Model {
 
     const DEFAULT_EXT: Option<&'static str> = Some("pp");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(parent: Option<usize>) -> Plane {
+        Plane {
+            name: "plane".to_owned(),
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            image: None,
+            offset: vec2::ZERO,
+            parent,
+            transform: mat4::identity(),
+        }
+    }
+
+    fn group(parent: Option<usize>) -> Group {
+        Group {
+            name: "group".to_owned(),
+            parent,
+            transform: mat4::identity(),
+        }
+    }
+
+    /// File as it was written by the given version, older ones were gzipped
+    fn versioned(version: u8, pp: &impl Serialize) -> Vec<u8> {
+        let mut file = HEADER.as_bytes().to_vec();
+        file.push(version);
+        if version < versions::v4::VERSION {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut file, flate2::Compression::default());
+            bincode::serialize_into(&mut encoder, pp).unwrap();
+            encoder.finish().unwrap();
+        } else {
+            bincode::serialize_into(&mut file, pp).unwrap();
+        }
+        file
+    }
+
+    fn pixels(size: vec2<usize>, seed: u8) -> document::Pixels {
+        let mut pixels = document::Pixels::new(size);
+        for (i, byte) in pixels.data.iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(7).wrapping_add(seed);
+        }
+        pixels
+    }
+
+    fn load(planes: Vec<Plane>, groups: Vec<Group>) -> Result<Document, LoadError> {
+        Document::try_from(Pp {
+            planes,
+            groups,
+            scene: None,
+        })
+    }
+
+    #[test]
+    fn save_and_load() {
+        let tile = pixels(vec2::splat(crate::Texture::TILE_SIZE), 1);
+        let document = Document {
+            planes: vec![
+                document::Plane {
+                    name: "pixels".to_owned(),
+                    visible: false,
+                    locked: true,
+                    opacity: 0.25,
+                    image: Some(document::Image::Pixels(pixels(vec2(3, 2), 2))),
+                    offset: vec2(-3, 5),
+                    parent: None,
+                    transform: mat4::translate(vec3(1.0, 2.0, 3.0)),
+                },
+                document::Plane {
+                    name: "tiles".to_owned(),
+                    visible: true,
+                    locked: false,
+                    opacity: 1.0,
+                    image: Some(document::Image::Tiles(vec![document::Tile {
+                        pos: vec2(-128, 256),
+                        pixels: tile.clone(),
+                    }])),
+                    offset: vec2::ZERO,
+                    parent: Some(0),
+                    transform: mat4::scale_uniform(2.0),
+                },
+                document::Plane {
+                    name: "linked".to_owned(),
+                    visible: true,
+                    locked: false,
+                    opacity: 1.0,
+                    image: Some(document::Image::Linked(1)),
+                    offset: vec2::ZERO,
+                    parent: Some(1),
+                    transform: mat4::identity(),
+                },
+            ],
+            groups: vec![
+                document::Group {
+                    name: "outer".to_owned(),
+                    parent: None,
+                    transform: mat4::translate(vec3(0.0, 0.0, 1.0)),
+                },
+                document::Group {
+                    name: "inner".to_owned(),
+                    parent: Some(0),
+                    transform: mat4::identity(),
+                },
+            ],
+            scene: Some(document::Scene {
+                camera: document::Camera {
+                    pos: vec3(1.0, 2.0, 3.0),
+                    rot: 0.5,
+                    attack: -0.25,
+                    distance: 10.0,
+                    fov: 1.0,
+                },
+                selected: Some(2),
+                color: Rgba::new(0.5, 0.25, 1.0, 1.0),
+                palette: vec![Rgba::WHITE, Rgba::BLACK],
+            }),
+        };
+        let mut file = Vec::new();
+        document.clone().save(&mut file).unwrap();
+        let (version, loaded) = Document::load_versioned(file.as_slice()).unwrap();
+        assert_eq!(version, VERSION);
+
+        assert_eq!(loaded.planes.len(), 3);
+        for (loaded, saved) in loaded.planes.iter().zip(&document.planes) {
+            assert_eq!(loaded.name, saved.name);
+            assert_eq!(loaded.visible, saved.visible);
+            assert_eq!(loaded.locked, saved.locked);
+            assert_eq!(loaded.opacity, saved.opacity);
+            assert_eq!(loaded.offset, saved.offset);
+            assert_eq!(loaded.parent, saved.parent);
+            assert_eq!(loaded.transform, saved.transform);
+        }
+        assert!(matches!(
+            &loaded.planes[0].image,
+            Some(document::Image::Pixels(image)) if *image == pixels(vec2(3, 2), 2)
+        ));
+        let Some(document::Image::Tiles(tiles)) = &loaded.planes[1].image else {
+            panic!("expected tiles");
+        };
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].pos, vec2(-128, 256));
+        assert_eq!(tiles[0].pixels, tile);
+        assert!(matches!(
+            loaded.planes[2].image,
+            Some(document::Image::Linked(1))
+        ));
+
+        let groups: Vec<_> = loaded
+            .groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.parent, group.transform))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("outer", None, mat4::translate(vec3(0.0, 0.0, 1.0))),
+                ("inner", Some(0), mat4::identity()),
+            ]
+        );
+
+        let scene = loaded.scene.unwrap();
+        assert_eq!(scene.camera.pos, vec3(1.0, 2.0, 3.0));
+        assert_eq!(scene.camera.rot, 0.5);
+        assert_eq!(scene.camera.attack, -0.25);
+        assert_eq!(scene.camera.distance, 10.0);
+        assert_eq!(scene.camera.fov, 1.0);
+        assert_eq!(scene.selected, Some(2));
+        assert_eq!(scene.color, Rgba::new(0.5, 0.25, 1.0, 1.0));
+        assert_eq!(scene.palette, [Rgba::WHITE, Rgba::BLACK]);
+    }
+
+    #[test]
+    fn v0_is_migrated() {
+        let data = pixels(vec2(1, 2), 3).data;
+        let v0 = versions::v0::Pp {
+            planes: vec![
+                versions::v0::Plane {
+                    image: Some(versions::v0::Image::Embed {
+                        size: vec2(1, 2),
+                        data: data.clone(),
+                    }),
+                    offset: vec2(-1, 4),
+                    transform: mat4::translate(vec3(5.0, 0.0, 0.0)),
+                },
+                versions::v0::Plane {
+                    image: Some(versions::v0::Image::Load("image.png".into())),
+                    offset: vec2::ZERO,
+                    transform: mat4::identity(),
+                },
+            ],
+        };
+        let mut file = versions::v0::HEADER.as_bytes().to_vec();
+        let mut encoder = flate2::write::GzEncoder::new(&mut file, flate2::Compression::default());
+        bincode::serialize_into(&mut encoder, &v0).unwrap();
+        encoder.finish().unwrap();
+
+        let (version, document) = Document::load_versioned(file.as_slice()).unwrap();
+        assert_eq!(version, 0);
+        assert!(document.groups.is_empty());
+        assert!(document.scene.is_none());
+        let [embedded, loaded] = document.planes.as_slice() else {
+            panic!("expected two planes");
+        };
+        // Properties added later get their defaults
+        assert_eq!(embedded.name, "");
+        assert!(embedded.visible);
+        assert!(!embedded.locked);
+        assert_eq!(embedded.opacity, 1.0);
+        assert_eq!(embedded.parent, None);
+        assert_eq!(embedded.offset, vec2(-1, 4));
+        assert_eq!(embedded.transform, mat4::translate(vec3(5.0, 0.0, 0.0)));
+        assert!(matches!(
+            &embedded.image,
+            Some(document::Image::Pixels(pixels)) if pixels.size == vec2(1, 2) && pixels.data == data
+        ));
+        assert!(matches!(
+            &loaded.image,
+            Some(document::Image::Load(path)) if *path == PathBuf::from("image.png")
+        ));
+    }
+
+    #[test]
+    fn v3_scene_is_kept() {
+        let v3 = versions::v3::Pp {
+            planes: vec![],
+            scene: Some(versions::v3::Scene {
+                camera: versions::v3::Camera {
+                    pos: vec3(1.0, 2.0, 3.0),
+                    rot: 0.5,
+                    attack: 0.25,
+                    distance: 7.0,
+                    fov: 1.5,
+                },
+                selected: None,
+                color: Rgba::new(1.0, 0.0, 0.0, 1.0),
+                palette: vec![Rgba::new(0.0, 1.0, 0.0, 1.0)],
+            }),
+        };
+        let file = versioned(versions::v3::VERSION, &v3);
+        let (version, document) = Document::load_versioned(file.as_slice()).unwrap();
+        assert_eq!(version, versions::v3::VERSION);
+        let scene = document.scene.unwrap();
+        assert_eq!(scene.camera.pos, vec3(1.0, 2.0, 3.0));
+        assert_eq!(scene.camera.distance, 7.0);
+        assert_eq!(scene.color, Rgba::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(scene.palette, [Rgba::new(0.0, 1.0, 0.0, 1.0)]);
+    }
+
+    #[test]
+    fn unknown_versions_and_headers_are_rejected() {
+        let file = versioned(VERSION + 1, &());
+        assert!(matches!(
+            Document::load(file.as_slice()),
+            Err(LoadError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+        assert!(matches!(
+            Document::load(b"VersionedPigPaint".as_slice()),
+            Err(LoadError::BadHeader)
+        ));
+    }
+
+    #[test]
+    fn v5_planes_are_not_grouped() {
+        let v5 = versions::v5::Pp {
+            planes: vec![versions::v5::Plane {
+                name: "old".to_owned(),
+                visible: true,
+                locked: true,
+                opacity: 0.5,
+                image: None,
+                offset: vec2(1, 2),
+                transform: mat4::translate(vec3(1.0, 2.0, 3.0)),
+            }],
+            scene: None,
+        };
+        let file = versioned(versions::v5::VERSION, &v5);
+        let (version, document) = Document::load_versioned(file.as_slice()).unwrap();
+        assert_eq!(version, versions::v5::VERSION);
+        assert!(document.groups.is_empty());
+        let [plane] = document.planes.as_slice() else {
+            panic!("expected one plane");
+        };
+        assert_eq!(plane.name, "old");
+        assert!(plane.locked);
+        assert_eq!(plane.offset, vec2(1, 2));
+        assert_eq!(plane.parent, None);
+        assert_eq!(plane.transform, mat4::translate(vec3(1.0, 2.0, 3.0)));
+    }
+
+    #[test]
+    fn embed_with_wrong_length_is_rejected() {
+        let image = Image::Embed {
+            size: vec2(2, 2),
+            data: vec![0; 15],
+        };
+        assert!(matches!(
+            document::Image::try_from(image),
+            Err(LoadError::BadEmbedSize { len: 15, .. })
+        ));
+        let image = Image::Embed {
+            size: vec2(usize::MAX, 2),
+            data: vec![],
+        };
+        assert!(matches!(
+            document::Image::try_from(image),
+            Err(LoadError::BadEmbedSize { len: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn tile_with_wrong_size_is_rejected() {
+        let pixels = document::Pixels::new(vec2(3, 5));
+        let image = Image::Tiles(vec![Tile {
+            pos: vec2(0, 128),
+            png: pixels.encode_png(),
+        }]);
+        assert!(matches!(
+            document::Image::try_from(image),
+            Err(LoadError::BadTileSize { size, .. }) if size == vec2(3, 5)
+        ));
+    }
+
+    #[test]
+    fn nested_groups_load() {
+        let document = load(
+            vec![plane(Some(1)), plane(None)],
+            vec![group(None), group(Some(0)), group(Some(1))],
+        )
+        .unwrap();
+        assert_eq!(document.planes[0].parent, Some(1));
+        assert_eq!(document.groups[2].parent, Some(1));
+    }
+
+    #[test]
+    fn missing_plane_parent_is_rejected() {
+        let result = load(vec![plane(None), plane(Some(1))], vec![group(None)]);
+        assert!(matches!(
+            result,
+            Err(LoadError::MissingPlaneParent { plane: 1, group: 1 })
+        ));
+    }
+
+    #[test]
+    fn missing_group_parent_is_rejected() {
+        let result = load(vec![], vec![group(None), group(Some(5))]);
+        assert!(matches!(
+            result,
+            Err(LoadError::MissingGroupParent {
+                group: 1,
+                parent: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn group_cycles_are_rejected() {
+        let result = load(vec![plane(Some(0))], vec![group(Some(0))]);
+        assert!(matches!(result, Err(LoadError::GroupCycle { group: 0 })));
+        let result = load(
+            vec![],
+            vec![group(None), group(Some(3)), group(Some(1)), group(Some(2))],
+        );
+        assert!(matches!(result, Err(LoadError::GroupCycle { group: 1 })));
+    }
+}
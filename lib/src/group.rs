@@ -0,0 +1,193 @@
+use super::*;
+
+/// Node that planes and other groups can be parented to
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Relative to the parent
+    pub transform: mat4<f32>,
+}
+
+impl Group {
+    pub fn new(parent: Option<usize>, transform: mat4<f32>) -> Self {
+        Self {
+            name: String::new(),
+            parent,
+            transform,
+        }
+    }
+
+    /// Name to show in the ui, unnamed groups are called by their index
+    pub fn display_name(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("group {index}")
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Anything in the hierarchy that has a transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Plane(usize),
+    Group(usize),
+}
+
+impl Model {
+    /// World transform of the group, identity for the root
+    pub fn parent_transform(&self, parent: Option<usize>) -> mat4<f32> {
+        match parent {
+            Some(index) => {
+                let group = &self.groups[index];
+                self.parent_transform(group.parent) * group.transform
+            }
+            None => mat4::identity(),
+        }
+    }
+
+    pub fn world_transform(&self, plane: usize) -> mat4<f32> {
+        self.node_world_transform(Node::Plane(plane))
+    }
+
+    pub fn node_parent(&self, node: Node) -> Option<usize> {
+        match node {
+            Node::Plane(index) => self.planes[index].parent,
+            Node::Group(index) => self.groups[index].parent,
+        }
+    }
+
+    /// Local transform relative to the parent
+    pub fn node_transform(&self, node: Node) -> mat4<f32> {
+        match node {
+            Node::Plane(index) => self.planes[index].transform,
+            Node::Group(index) => self.groups[index].transform,
+        }
+    }
+
+    pub fn set_node_transform(&mut self, node: Node, transform: mat4<f32>) {
+        self.invalidate_raycast_index();
+        match node {
+            Node::Plane(index) => self.planes[index].transform = transform,
+            Node::Group(index) => self.groups[index].transform = transform,
+        }
+    }
+
+    pub fn node_world_transform(&self, node: Node) -> mat4<f32> {
+        self.parent_transform(self.node_parent(node)) * self.node_transform(node)
+    }
+
+    /// Changes the local transform so that the node ends up at the given world transform
+    pub fn set_node_world_transform(&mut self, node: Node, transform: mat4<f32>) {
+        let parent = self.parent_transform(self.node_parent(node));
+        self.set_node_transform(node, parent.inverse() * transform);
+    }
+
+    /// Only changes the parent, the local transform stays the same
+    pub fn set_node_parent(&mut self, node: Node, parent: Option<usize>) {
+        self.invalidate_raycast_index();
+        match node {
+            Node::Plane(index) => self.planes[index].parent = parent,
+            Node::Group(index) => self.groups[index].parent = parent,
+        }
+    }
+
+    /// Whether the node is inside of the group, directly or through other groups
+    pub fn is_in_group(&self, node: Node, group: usize) -> bool {
+        let mut parent = self.node_parent(node);
+        while let Some(index) = parent {
+            if index == group {
+                return true;
+            }
+            parent = self.groups[index].parent;
+        }
+        false
+    }
+
+    /// Number of groups above the node
+    pub fn depth(&self, node: Node) -> usize {
+        let mut depth = 0;
+        let mut parent = self.node_parent(node);
+        while let Some(index) = parent {
+            depth += 1;
+            parent = self.groups[index].parent;
+        }
+        depth
+    }
+
+    /// Inserts the group keeping the parents of every node pointing to the same groups
+    pub fn insert_group(&mut self, index: usize, group: Group) {
+        self.invalidate_raycast_index();
+        let shift = |parent: &mut Option<usize>| {
+            if let Some(parent) = parent {
+                if *parent >= index {
+                    *parent += 1;
+                }
+            }
+        };
+        self.planes
+            .iter_mut()
+            .for_each(|plane| shift(&mut plane.parent));
+        self.groups
+            .iter_mut()
+            .for_each(|group| shift(&mut group.parent));
+        self.groups.insert(index, group);
+    }
+
+    /// Removes the group, nodes inside of it are moved to its parent
+    /// keeping their local transforms
+    pub fn remove_group(&mut self, index: usize) -> Group {
+        self.invalidate_raycast_index();
+        let group = self.groups.remove(index);
+        let shift = |parent: &mut Option<usize>| {
+            *parent = parent_after_removal(*parent, index, group.parent);
+        };
+        self.planes
+            .iter_mut()
+            .for_each(|plane| shift(&mut plane.parent));
+        self.groups
+            .iter_mut()
+            .for_each(|group| shift(&mut group.parent));
+        group
+    }
+}
+
+/// Where a parent points once the group at the index is removed,
+/// nodes inside of it go to the parent of the removed group
+fn parent_after_removal(
+    parent: Option<usize>,
+    index: usize,
+    group_parent: Option<usize>,
+) -> Option<usize> {
+    let shift = |parent: Option<usize>| parent.map(|p| if p > index { p - 1 } else { p });
+    if parent == Some(index) {
+        shift(group_parent)
+    } else {
+        shift(parent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removing_a_group_keeps_parents() {
+        // Group 1 is inside of group 2, which comes after it
+        let removed = 1;
+        let group_parent = Some(2);
+        let parents = [None, Some(0), Some(1), Some(2), Some(3)];
+        let after: Vec<Option<usize>> = parents
+            .into_iter()
+            .map(|parent| parent_after_removal(parent, removed, group_parent))
+            .collect();
+        assert_eq!(after, [None, Some(0), Some(1), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn removing_a_top_level_group() {
+        assert_eq!(parent_after_removal(Some(0), 0, None), None);
+        assert_eq!(parent_after_removal(Some(1), 0, None), Some(0));
+    }
+}
@@ -1,16 +1,25 @@
 use geng::prelude::*;
 
+pub mod document;
+pub mod export;
 pub mod file_format;
+mod group;
 mod plane;
+mod raycast;
 mod texture;
 
+pub use document::Document;
+pub use group::*;
 pub use plane::*;
+pub use raycast::RaycastOptions;
 pub use texture::*;
 
 #[derive(Clone)]
 pub struct Model {
     ugli: Ugli,
     pub planes: Vec<Plane>,
+    pub groups: Vec<Group>,
+    raycast_index: RefCell<raycast::RaycastIndex>,
 }
 
 impl Model {
@@ -18,6 +27,41 @@ impl Model {
         Self {
             ugli: ugli.clone(),
             planes: vec![],
+            groups: vec![],
+            raycast_index: default(),
         }
     }
+
+    pub fn trim(&mut self) {
+        for plane in &mut self.planes {
+            plane.texture.borrow_mut().trim();
+        }
+    }
+
+    /// Other planes sharing the texture with the given one
+    pub fn linked_planes(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let plane = &self.planes[index];
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(move |&(other, other_plane)| other != index && other_plane.is_linked_to(plane))
+            .map(|(other, _plane)| other)
+    }
+
+    /// Indices of the planes after it are shifted, like [Vec::insert]
+    pub fn insert_plane(&mut self, index: usize, plane: Plane) {
+        self.invalidate_raycast_index();
+        self.planes.insert(index, plane);
+    }
+
+    pub fn remove_plane(&mut self, index: usize) -> Plane {
+        self.invalidate_raycast_index();
+        self.planes.remove(index)
+    }
+
+    /// Removes the plane from one index and inserts it at another
+    pub fn move_plane(&mut self, from: usize, to: usize) {
+        let plane = self.remove_plane(from);
+        self.insert_plane(to, plane);
+    }
 }
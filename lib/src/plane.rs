@@ -1,8 +1,19 @@
 use super::*;
 
+/// Texture that can be shared by several planes, painting one of them changes all
+pub type SharedTexture = Rc<RefCell<Texture>>;
+
+/// Clones are linked, they share the texture, see [Plane::unlinked]
 #[derive(Clone)]
 pub struct Plane {
-    pub texture: Texture,
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+    pub texture: SharedTexture,
+    /// Group that the plane is in
+    pub parent: Option<usize>,
+    /// Relative to the parent, see [Model::world_transform]
     pub transform: mat4<f32>,
 }
 
@@ -12,22 +23,66 @@ pub struct Raycast {
 }
 
 impl Plane {
-    pub fn raycast(&self, ray: geng::camera::Ray) -> Option<Raycast> {
-        let inv_transform = self.transform.inverse();
-        let local_ray = geng::camera::Ray {
-            from: (inv_transform * ray.from.extend(1.0)).into_3d(),
-            dir: (inv_transform * ray.dir.extend(0.0)).xyz(),
-        };
-        if local_ray.dir.z.approx_eq(&0.0) {
-            return None;
+    pub fn new(texture: Texture, transform: mat4<f32>) -> Self {
+        Self {
+            name: String::new(),
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+            texture: Rc::new(RefCell::new(texture)),
+            parent: None,
+            transform,
+        }
+    }
+
+    /// Copy of the plane with its own copy of the texture
+    pub fn unlinked(&self) -> Self {
+        Self {
+            texture: Rc::new(RefCell::new(self.texture.borrow().clone())),
+            ..self.clone()
         }
-        let t = -local_ray.from.z / local_ray.dir.z;
-        if t <= 0.0 {
-            return None;
+    }
+
+    pub fn is_linked_to(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.texture, &other.texture)
+    }
+
+    /// Hidden and locked planes can not be painted on or transformed
+    pub fn editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+
+    /// Name to show in the ui, unnamed planes are called by their index
+    pub fn display_name(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("plane {index}")
+        } else {
+            self.name.clone()
         }
-        Some(Raycast {
-            texture_pos: (local_ray.from + local_ray.dir * t).xy(),
-            t,
-        })
     }
+
+    /// Takes the world transform since the plane does not know its parents
+    pub fn raycast(&self, transform: mat4<f32>, ray: geng::camera::Ray) -> Option<Raycast> {
+        raycast_transformed(transform, ray)
+    }
+}
+
+/// Where the ray hits the z=0 plane of the transform, in front of the ray start
+pub(crate) fn raycast_transformed(transform: mat4<f32>, ray: geng::camera::Ray) -> Option<Raycast> {
+    let inv_transform = transform.inverse();
+    let local_ray = geng::camera::Ray {
+        from: (inv_transform * ray.from.extend(1.0)).into_3d(),
+        dir: (inv_transform * ray.dir.extend(0.0)).xyz(),
+    };
+    if local_ray.dir.z.approx_eq(&0.0) {
+        return None;
+    }
+    let t = -local_ray.from.z / local_ray.dir.z;
+    if t <= 0.0 {
+        return None;
+    }
+    Some(Raycast {
+        texture_pos: (local_ray.from + local_ray.dir * t).xy(),
+        t,
+    })
 }
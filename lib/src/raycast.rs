@@ -0,0 +1,308 @@
+use super::*;
+
+/// Which hits [Model::raycast] reports
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastOptions {
+    /// Skip transparent pixels, otherwise the whole infinite plane can be hit
+    pub opaque_only: bool,
+    pub include_hidden: bool,
+}
+
+impl Default for RaycastOptions {
+    fn default() -> Self {
+        Self {
+            opaque_only: true,
+            include_hidden: false,
+        }
+    }
+}
+
+/// World space axis aligned box
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: vec3<f32>,
+    max: vec3<f32>,
+}
+
+impl Bounds {
+    /// Flat planes have zero thickness, so the boxes are padded a little
+    const PADDING: f32 = 1e-3;
+
+    fn points(points: impl IntoIterator<Item = vec3<f32>>) -> Option<Self> {
+        points
+            .into_iter()
+            .map(|p| Self { min: p, max: p })
+            .reduce(Self::union)
+            .map(|bounds| Self {
+                min: bounds.min - vec3::splat(Self::PADDING),
+                max: bounds.max + vec3::splat(Self::PADDING),
+            })
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            min: vec3(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: vec3(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    fn center(&self) -> vec3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Distance along the ray to where it enters the box
+    fn hit(&self, ray: geng::camera::Ray) -> Option<f32> {
+        let axes = |v: vec3<f32>| [v.x, v.y, v.z];
+        let (min, max, from, dir) = (
+            axes(self.min),
+            axes(self.max),
+            axes(ray.from),
+            axes(ray.dir),
+        );
+        let mut enter = 0.0f32;
+        let mut exit = f32::INFINITY;
+        for axis in 0..3 {
+            let t1 = (min[axis] - from[axis]) / dir[axis];
+            let t2 = (max[axis] - from[axis]) / dir[axis];
+            // NaN happens when the ray lies exactly on a side and is ignored by min/max
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+        }
+        (enter <= exit).then_some(enter)
+    }
+}
+
+#[derive(Clone)]
+enum BvhNode {
+    Leaf {
+        plane: usize,
+        bounds: Bounds,
+    },
+    Branch {
+        bounds: Bounds,
+        children: Box<[BvhNode; 2]>,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> Bounds {
+        match self {
+            Self::Leaf { bounds, .. } | Self::Branch { bounds, .. } => *bounds,
+        }
+    }
+
+    /// Splits in half along the longest axis until every plane has its own leaf
+    fn build(mut leaves: Vec<(usize, Bounds)>) -> Option<Self> {
+        if leaves.len() <= 1 {
+            return leaves
+                .pop()
+                .map(|(plane, bounds)| Self::Leaf { plane, bounds });
+        }
+        let bounds = leaves
+            .iter()
+            .map(|&(_plane, bounds)| bounds)
+            .reduce(Bounds::union)
+            .unwrap();
+        let size = bounds.max - bounds.min;
+        let key: fn(vec3<f32>) -> f32 = if size.x >= size.y && size.x >= size.z {
+            |v| v.x
+        } else if size.y >= size.z {
+            |v| v.y
+        } else {
+            |v| v.z
+        };
+        leaves.sort_by_key(|(_plane, bounds)| r32(key(bounds.center())));
+        let right = leaves.split_off(leaves.len() / 2);
+        Some(Self::Branch {
+            bounds,
+            children: Box::new([Self::build(leaves)?, Self::build(right)?]),
+        })
+    }
+}
+
+/// Bounding volume hierarchy over the allocated area of every plane
+///
+/// Kept along with the texture generations it was built from, transforms are not compared
+/// since that means walking up the groups of every plane, changing them marks it as stale instead
+#[derive(Clone, Default)]
+pub(crate) struct RaycastIndex {
+    generations: Vec<u64>,
+    stale: bool,
+    root: Option<BvhNode>,
+}
+
+impl RaycastIndex {
+    /// Planes that have nothing allocated are left out
+    fn build(planes: impl IntoIterator<Item = (mat4<f32>, Option<Aabb2<i32>>)>) -> Option<BvhNode> {
+        let leaves = planes
+            .into_iter()
+            .enumerate()
+            .filter_map(|(plane, (transform, bb))| {
+                let bb = bb?.map(|x| x as f32);
+                let bounds = Bounds::points(
+                    bb.corners()
+                        .into_iter()
+                        .map(|corner| (transform * corner.extend(0.0).extend(1.0)).into_3d()),
+                )?;
+                Some((plane, bounds))
+            })
+            .collect();
+        BvhNode::build(leaves)
+    }
+
+    /// Closest of the hits, only planes with boxes along the ray are checked
+    fn closest(
+        root: Option<&BvhNode>,
+        ray: geng::camera::Ray,
+        hit: impl Fn(usize) -> Option<Raycast>,
+    ) -> Option<(usize, Raycast)> {
+        let mut stack: Vec<&BvhNode> = root.into_iter().collect();
+        let mut closest: Option<(usize, Raycast)> = None;
+        while let Some(node) = stack.pop() {
+            let closest_t = closest
+                .as_ref()
+                .map_or(f32::INFINITY, |(_idx, raycast)| raycast.t);
+            match node.bounds().hit(ray) {
+                Some(t) if t <= closest_t => {}
+                _ => continue,
+            }
+            match node {
+                BvhNode::Leaf { plane, .. } => {
+                    if let Some(raycast) = hit(*plane) {
+                        if raycast.t < closest_t {
+                            closest = Some((*plane, raycast));
+                        }
+                    }
+                }
+                BvhNode::Branch { children, .. } => stack.extend(children.iter()),
+            }
+        }
+        closest
+    }
+}
+
+impl Model {
+    /// Has to be called after changing transforms, parents or the order of planes,
+    /// new planes and tiles are noticed without it
+    pub(crate) fn invalidate_raycast_index(&mut self) {
+        self.raycast_index.get_mut().stale = true;
+    }
+
+    fn update_raycast_index(&self) {
+        let generations: Vec<u64> = self
+            .planes
+            .iter()
+            .map(|plane| plane.texture.borrow().generation())
+            .collect();
+        let mut index = self.raycast_index.borrow_mut();
+        if !index.stale && index.generations == generations {
+            return;
+        }
+        *index = RaycastIndex {
+            root: RaycastIndex::build((0..self.planes.len()).map(|idx| {
+                (
+                    self.world_transform(idx),
+                    self.planes[idx].texture.borrow().allocated_bounds(),
+                )
+            })),
+            generations,
+            stale: false,
+        };
+    }
+
+    /// Closest plane hit by the ray
+    pub fn raycast(
+        &self,
+        ray: geng::camera::Ray,
+        options: RaycastOptions,
+    ) -> Option<(usize, Raycast)> {
+        let hit = |idx: usize| {
+            let plane = &self.planes[idx];
+            if !options.include_hidden && !plane.visible {
+                return None;
+            }
+            let raycast = plane.raycast(self.world_transform(idx), ray)?;
+            if options.opaque_only && plane.texture.borrow().color_at(raycast.texture_pos).a == 0.0
+            {
+                return None;
+            }
+            Some(raycast)
+        };
+
+        // Transparent hits can be anywhere on the plane, so only opaque ones can use the index
+        if !options.opaque_only {
+            return (0..self.planes.len())
+                .filter_map(|idx| Some((idx, hit(idx)?)))
+                .min_by_key(|(_idx, raycast)| r32(raycast.t));
+        }
+
+        self.update_raycast_index();
+        let index = self.raycast_index.borrow();
+        RaycastIndex::closest(index.root.as_ref(), ray, hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic numbers in -1..1, good enough to scatter planes around
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        }
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut random = Lcg(42);
+        let planes: Vec<(mat4<f32>, Option<Aabb2<i32>>)> = (0..50)
+            .map(|i| {
+                let pos = vec3(random.next(), random.next(), random.next()) * 100.0;
+                let transform = mat4::translate(pos)
+                    * mat4::rotate_x(Angle::from_radians(random.next() * 3.0))
+                    * mat4::rotate_y(Angle::from_radians(random.next() * 3.0));
+                let min = vec2(random.next(), random.next()).map(|x| (x * 50.0) as i32);
+                let size = vec2(random.next(), random.next()).map(|x| (x * 20.0 + 21.0) as i32);
+                // Some planes have nothing drawn on them
+                let bb = (i % 7 != 0).then(|| Aabb2::point(min).extend_positive(size));
+                (transform, bb)
+            })
+            .collect();
+        let root = RaycastIndex::build(planes.iter().copied());
+        for _ in 0..1000 {
+            let ray = geng::camera::Ray {
+                from: vec3(random.next(), random.next(), random.next()) * 200.0,
+                dir: vec3(random.next(), random.next(), random.next()),
+            };
+            let hit = |idx: usize| {
+                let (transform, bb) = planes[idx];
+                let bb = bb?.map(|x| x as f32);
+                let raycast = raycast_transformed(transform, ray)?;
+                let pos = raycast.texture_pos;
+                (bb.min.x <= pos.x && pos.x < bb.max.x && bb.min.y <= pos.y && pos.y < bb.max.y)
+                    .then_some(raycast)
+            };
+            let expected = (0..planes.len())
+                .filter_map(|idx| Some((idx, hit(idx)?)))
+                .min_by_key(|(_idx, raycast)| r32(raycast.t))
+                .map(|(idx, _raycast)| idx);
+            let actual = RaycastIndex::closest(root.as_ref(), ray, hit).map(|(idx, _raycast)| idx);
+            assert_eq!(actual, expected);
+        }
+    }
+}
@@ -1,129 +1,313 @@
 use super::*;
 
+/// Sparse set of fixed size tiles, allocated when something is drawn into them
+///
+/// Every tile keeps a cpu side copy of its pixels so that hit testing and saving
+/// do not need to read back from the gpu
 pub struct Texture {
     ugli: Ugli,
-    pub texture: Option<ugli::Texture>,
-    pub offset: vec2<i32>,
+    tiles: HashMap<vec2<i32>, Tile>,
+    /// Changes whenever tiles are allocated or freed, never the same for two textures
+    generation: u64,
+}
+
+fn next_generation() -> u64 {
+    static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+struct Tile {
+    texture: ugli::Texture,
+    pixels: document::Pixels,
+    /// Cached [document::Pixels::opaque_bounds], kept up to date on every write
+    opaque: Option<Aabb2<usize>>,
+}
+
+impl Tile {
+    fn new(ugli: &Ugli, pixels: document::Pixels) -> Self {
+        let mut texture = ugli::Texture::new_uninitialized(ugli, pixels.size);
+        texture.sub_image(vec2::ZERO, pixels.size, &pixels.data);
+        texture.set_filter(ugli::Filter::Nearest);
+        Self {
+            texture,
+            opaque: pixels.opaque_bounds(),
+            pixels,
+        }
+    }
 }
 
 impl Clone for Texture {
     fn clone(&self) -> Self {
         Self {
             ugli: self.ugli.clone(),
-            texture: self.texture.as_ref().map(|texture| {
-                let mut new = ugli::Texture::new_uninitialized(&self.ugli, texture.size());
-                let framebuffer = ugli::FramebufferRead::new_color(
-                    &self.ugli,
-                    ugli::ColorAttachmentRead::Texture(texture),
-                );
-                framebuffer.copy_to_texture(
-                    &mut new,
-                    Aabb2::ZERO.extend_positive(framebuffer.size()),
-                    vec2::ZERO,
-                );
-                new.set_filter(ugli::Filter::Nearest);
-                new
-            }),
-            offset: self.offset,
+            tiles: self
+                .tiles
+                .iter()
+                .map(|(&index, tile)| (index, Tile::new(&self.ugli, tile.pixels.clone())))
+                .collect(),
+            generation: next_generation(),
         }
     }
 }
 
 impl Texture {
-    pub const MAX_SIZE: usize = 2048;
+    pub const TILE_SIZE: usize = 128;
 
     pub fn new(ugli: &Ugli) -> Self {
         Self {
             ugli: ugli.clone(),
-            texture: None,
-            offset: vec2::ZERO,
+            tiles: HashMap::new(),
+            generation: next_generation(),
         }
     }
 
-    pub fn from(ugli: &Ugli, texture: Option<ugli::Texture>, offset: vec2<i32>) -> Self {
-        Self {
-            ugli: ugli.clone(),
-            offset,
-            texture,
-        }
+    pub fn from_pixels(ugli: &Ugli, pixels: &document::Pixels, offset: vec2<i32>) -> Self {
+        let mut result = Self::new(ugli);
+        result.write_pixels(offset, pixels);
+        result
     }
 
-    pub fn draw(&mut self, area: Aabb2<i32>, f: impl FnOnce(&mut ugli::Framebuffer, Aabb2<usize>)) {
-        self.ensure_bounds(area);
-        let Some(texture) = &mut self.texture else {
-            return;
+    pub fn from_texture(ugli: &Ugli, texture: &ugli::Texture, offset: vec2<i32>) -> Self {
+        let framebuffer =
+            ugli::FramebufferRead::new_color(ugli, ugli::ColorAttachmentRead::Texture(texture));
+        let pixels = document::Pixels {
+            size: texture.size(),
+            data: framebuffer.read_color().data().to_vec(),
         };
-        let mut framebuffer =
-            ugli::Framebuffer::new_color(&self.ugli, ugli::ColorAttachment::Texture(texture));
-        f(
-            &mut framebuffer,
-            area.map_bounds(|p| (p - self.offset).map(|x| x as usize)),
-        );
+        Self::from_pixels(ugli, &pixels, offset)
     }
 
-    pub fn bounding_box(&self) -> Option<Aabb2<i32>> {
-        self.texture.as_ref().map(|texture| {
-            Aabb2::point(self.offset).extend_positive(texture.size().map(|x| x as _))
-        })
+    fn tile_bounds(index: vec2<i32>) -> Aabb2<i32> {
+        let size = Self::TILE_SIZE as i32;
+        Aabb2::point(index * size).extend_positive(vec2::splat(size))
     }
 
-    fn ensure_bounds(&mut self, bb: Aabb2<i32>) {
-        let old_bb = self.bounding_box().unwrap_or(Aabb2::ZERO);
-        let new_bb = Aabb2 {
-            min: old_bb.min.zip(bb.min).map(|(a, b)| i32::min(a, b)),
-            max: old_bb.max.zip(bb.max).map(|(a, b)| i32::max(a, b)),
-        };
-        if new_bb.width() as usize > Self::MAX_SIZE || new_bb.height() as usize > Self::MAX_SIZE {
+    /// Indices of the tiles that intersect the area
+    fn tile_indices(area: Aabb2<i32>) -> impl Iterator<Item = vec2<i32>> {
+        let size = Self::TILE_SIZE as i32;
+        let min = area.min.map(|x| x.div_euclid(size));
+        let max = (area.max - vec2::splat(1)).map(|x| x.div_euclid(size));
+        (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| vec2(x, y)))
+    }
+
+    fn tile_mut(&mut self, index: vec2<i32>) -> &mut Tile {
+        if !self.tiles.contains_key(&index) {
+            self.generation = next_generation();
+        }
+        let ugli = &self.ugli;
+        self.tiles
+            .entry(index)
+            .or_insert_with(|| Tile::new(ugli, document::Pixels::new(vec2::splat(Self::TILE_SIZE))))
+    }
+
+    /// Allocated tiles along with the area they cover
+    pub fn tiles(&self) -> impl Iterator<Item = (Aabb2<i32>, &ugli::Texture)> {
+        self.tiles
+            .iter()
+            .map(|(&index, tile)| (Self::tile_bounds(index), &tile.texture))
+    }
+
+    /// Replaces the pixels in the area starting at pos
+    pub fn write_pixels(&mut self, pos: vec2<i32>, pixels: &document::Pixels) {
+        let area = Aabb2::point(pos).extend_positive(pixels.size.map(|x| x as i32));
+        if area.width() <= 0 || area.height() <= 0 {
             return;
         }
-        if old_bb != new_bb {
-            let mut new_texture =
-                ugli::Texture::new_uninitialized(&self.ugli, new_bb.size().map(|x| x as usize));
-            new_texture.set_filter(ugli::Filter::Nearest);
-            {
-                let mut framebuffer = ugli::Framebuffer::new_color(
-                    &self.ugli,
-                    ugli::ColorAttachment::Texture(&mut new_texture),
-                );
-                let framebuffer = &mut framebuffer;
-                ugli::clear(framebuffer, Some(Rgba::TRANSPARENT_BLACK), None, None);
-            }
-            if let Some(texture) = &self.texture {
-                let framebuffer = ugli::FramebufferRead::new_color(
-                    &self.ugli,
-                    ugli::ColorAttachmentRead::Texture(texture),
-                );
-                framebuffer.copy_to_texture(
-                    &mut new_texture,
-                    Aabb2::ZERO.extend_positive(framebuffer.size()),
-                    (self.offset - new_bb.min).map(|x| x as usize),
-                );
+        for index in Self::tile_indices(area) {
+            let bounds = Self::tile_bounds(index);
+            let rect = intersect(bounds, area);
+            let part = pixels.sub(rect.map_bounds(|p| (p - pos).map(|x| x as usize)));
+            let tile = self.tile_mut(index);
+            let tile_pos = (rect.min - bounds.min).map(|x| x as usize);
+            tile.texture.sub_image(tile_pos, part.size, &part.data);
+            tile.pixels.blit(tile_pos, &part);
+            tile.opaque = tile.pixels.opaque_bounds();
+        }
+    }
+
+    /// Replaces a whole tile, freeing it when there are no pixels, see [TileChange]
+    pub fn set_tile(&mut self, index: vec2<i32>, pixels: Option<&document::Pixels>) {
+        match pixels {
+            Some(pixels) => self.write_pixels(Self::tile_bounds(index).min, pixels),
+            None => {
+                if self.tiles.remove(&index).is_some() {
+                    self.generation = next_generation();
+                }
             }
-            self.texture = Some(new_texture);
-            self.offset = new_bb.min;
         }
     }
 
+    /// Reads the pixels in the area, unallocated tiles are transparent
+    pub fn read_pixels(&self, area: Aabb2<i32>) -> document::Pixels {
+        let mut result = document::Pixels::new(area.size().map(|x| x.max(0) as usize));
+        if area.width() <= 0 || area.height() <= 0 {
+            return result;
+        }
+        for index in Self::tile_indices(area) {
+            let Some(tile) = self.tiles.get(&index) else {
+                continue;
+            };
+            let bounds = Self::tile_bounds(index);
+            let rect = intersect(bounds, area);
+            result.blit(
+                (rect.min - area.min).map(|x| x as usize),
+                &tile
+                    .pixels
+                    .sub(rect.map_bounds(|p| (p - bounds.min).map(|x| x as usize))),
+            );
+        }
+        result
+    }
+
+    /// Non-transparent tiles as cpu side pixels
+    pub fn to_tiles(&self) -> Vec<document::Tile> {
+        let mut tiles: Vec<document::Tile> = self
+            .tiles
+            .iter()
+            .filter(|(_index, tile)| tile.opaque.is_some())
+            .map(|(&index, tile)| document::Tile {
+                pos: Self::tile_bounds(index).min,
+                pixels: tile.pixels.clone(),
+            })
+            .collect();
+        tiles.sort_by_key(|tile| (tile.pos.y, tile.pos.x));
+        tiles
+    }
+
+    /// Bounds of the non-transparent pixels, cheap since every tile keeps its own
+    pub fn bounding_box(&self) -> Option<Aabb2<i32>> {
+        self.tiles
+            .iter()
+            .filter_map(|(&index, tile)| {
+                let min = Self::tile_bounds(index).min;
+                let bb = tile.opaque?;
+                Some(bb.map_bounds(|p| min + p.map(|x| x as i32)))
+            })
+            .reduce(union)
+    }
+
+    /// Smallest area made of whole tiles that contains the given one
+    pub fn tile_aligned(area: Aabb2<i32>) -> Aabb2<i32> {
+        Self::tile_indices(area)
+            .map(Self::tile_bounds)
+            .reduce(union)
+            .unwrap_or(area)
+    }
+
+    /// Area covered by the allocated tiles, cheaper than [Texture::bounding_box]
+    pub fn allocated_bounds(&self) -> Option<Aabb2<i32>> {
+        self.tiles().map(|(bb, _texture)| bb).reduce(union)
+    }
+
+    /// Bytes of pixel data kept for the allocated tiles, on the cpu and again on the gpu
+    pub fn memory_usage(&self) -> usize {
+        self.tiles.len() * Self::TILE_SIZE * Self::TILE_SIZE * 4
+    }
+
+    /// Frees the tiles that have nothing drawn in them
+    pub fn trim(&mut self) {
+        let count = self.tiles.len();
+        self.tiles.retain(|_index, tile| tile.opaque.is_some());
+        if self.tiles.len() != count {
+            self.generation = next_generation();
+        }
+    }
+
+    /// Same as long as the allocated tiles stay the same, see [Texture::allocated_bounds]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     pub fn color_at(&self, pos: vec2<f32>) -> Rgba<f32> {
-        let Some(texture) = &self.texture else {
+        let pos = pos.map(|x| x.floor() as i32);
+        let index = pos.map(|x| x.div_euclid(Self::TILE_SIZE as i32));
+        let Some(tile) = self.tiles.get(&index) else {
             return Rgba::TRANSPARENT_BLACK;
         };
-        let pos = pos.map(|x| x.floor() as i32);
-        let uv = pos - self.offset;
-        if Aabb2::ZERO
-            .extend_positive(texture.size().map(|x| x as _))
-            .contains(uv)
-        {
-            let framebuffer = ugli::FramebufferRead::new_color(
-                &self.ugli,
-                ugli::ColorAttachmentRead::Texture(texture),
-            );
-            let data = framebuffer.read_color_at(
-                Aabb2::point(uv.map(|x| x as usize)).extend_positive(vec2::splat(1)),
-            );
-            data.get(0, 0).convert()
-        } else {
-            Rgba::TRANSPARENT_BLACK
+        let pos = pos - Self::tile_bounds(index).min;
+        tile.pixels.get(pos.map(|x| x as usize)).convert()
+    }
+}
+
+/// Remembers the original pixels of the tiles touched by a sequence of draws
+#[derive(Default)]
+pub struct TextureEdit {
+    /// None for the tiles that were not allocated
+    original: HashMap<vec2<i32>, Option<document::Pixels>>,
+}
+
+/// Pixels of a tile before and after an edit, None when it is not allocated
+#[derive(Debug, Clone)]
+pub struct TileChange {
+    pub index: vec2<i32>,
+    pub before: Option<document::Pixels>,
+    pub after: Option<document::Pixels>,
+}
+
+impl TextureEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Must be called before drawing into the area
+    pub fn touch(&mut self, texture: &Texture, area: Aabb2<i32>) {
+        if area.width() <= 0 || area.height() <= 0 {
+            return;
+        }
+        for index in Texture::tile_indices(area) {
+            self.original
+                .entry(index)
+                .or_insert_with(|| texture.tiles.get(&index).map(|tile| tile.pixels.clone()));
         }
     }
+
+    /// Pixels of the area as they were before the edit
+    pub fn original(&self, texture: &Texture, area: Aabb2<i32>) -> document::Pixels {
+        let mut result = texture.read_pixels(area);
+        for (&index, pixels) in &self.original {
+            let bounds = Texture::tile_bounds(index);
+            let rect = intersect(bounds, area);
+            if rect.width() <= 0 || rect.height() <= 0 {
+                continue;
+            }
+            let part = match pixels {
+                Some(pixels) => {
+                    pixels.sub(rect.map_bounds(|p| (p - bounds.min).map(|x| x as usize)))
+                }
+                None => document::Pixels::new(rect.size().map(|x| x as usize)),
+            };
+            result.blit((rect.min - area.min).map(|x| x as usize), &part);
+        }
+        result
+    }
+
+    /// Touched tiles that have changed, so that undo restores or frees exactly those
+    pub fn finish(self, texture: &Texture) -> Vec<TileChange> {
+        let mut changes: Vec<TileChange> = self
+            .original
+            .into_iter()
+            .map(|(index, before)| TileChange {
+                index,
+                before,
+                after: texture.tiles.get(&index).map(|tile| tile.pixels.clone()),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        changes.sort_by_key(|change| (change.index.y, change.index.x));
+        changes
+    }
+}
+
+fn intersect(a: Aabb2<i32>, b: Aabb2<i32>) -> Aabb2<i32> {
+    Aabb2 {
+        min: a.min.zip(b.min).map(|(a, b)| a.max(b)),
+        max: a.max.zip(b.max).map(|(a, b)| a.min(b)),
+    }
+}
+
+fn union(a: Aabb2<i32>, b: Aabb2<i32>) -> Aabb2<i32> {
+    Aabb2 {
+        min: a.min.zip(b.min).map(|(a, b)| a.min(b)),
+        max: a.max.zip(b.max).map(|(a, b)| a.max(b)),
+    }
 }
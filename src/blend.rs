@@ -0,0 +1,126 @@
+use super::*;
+
+/// How painted colors are combined with the pixels under them, all colors have straight alpha
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Add,
+    /// Paints under the existing pixels, so only shows where they are not opaque
+    Behind,
+}
+
+impl BlendMode {
+    pub const ALL: [Self; 4] = [Self::Normal, Self::Multiply, Self::Add, Self::Behind];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Multiply => "multiply",
+            Self::Add => "add",
+            Self::Behind => "behind",
+        }
+    }
+
+    pub fn blend(self, dst: Rgba<f32>, src: Rgba<f32>) -> Rgba<f32> {
+        let mix = |s: f32, d: f32| match self {
+            Self::Multiply => s * d,
+            Self::Add => (s + d).min(1.0),
+            Self::Normal | Self::Behind => s,
+        };
+        match self {
+            Self::Behind => over(src, dst),
+            _ => {
+                // Over transparent pixels the color is painted as is
+                let channel = |s: f32, d: f32| s + (mix(s, d) - s) * dst.a;
+                let src = Rgba::new(
+                    channel(src.r, dst.r),
+                    channel(src.g, dst.g),
+                    channel(src.b, dst.b),
+                    src.a,
+                );
+                over(dst, src)
+            }
+        }
+    }
+}
+
+/// Source over destination
+pub fn over(dst: Rgba<f32>, src: Rgba<f32>) -> Rgba<f32> {
+    let a = src.a + dst.a * (1.0 - src.a);
+    if a == 0.0 {
+        return Rgba::TRANSPARENT_BLACK;
+    }
+    let channel = |s: f32, d: f32| (s * src.a + d * dst.a * (1.0 - src.a)) / a;
+    Rgba::new(
+        channel(src.r, dst.r),
+        channel(src.g, dst.g),
+        channel(src.b, dst.b),
+        a,
+    )
+}
+
+/// Lowers the alpha by the given amount, fully erased pixels become transparent black
+pub fn erase(dst: Rgba<f32>, amount: f32) -> Rgba<f32> {
+    let a = dst.a * (1.0 - amount);
+    if a <= 0.0 {
+        return Rgba::TRANSPARENT_BLACK;
+    }
+    Rgba { a, ..dst }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Rgba<f32>, b: Rgba<f32>) {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        assert!(
+            close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b) && close(a.a, b.a),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn opaque_source_replaces_destination() {
+        let dst = Rgba::new(0.2, 0.4, 0.6, 0.5);
+        let src = Rgba::new(1.0, 0.0, 0.0, 1.0);
+        assert_close(over(dst, src), src);
+    }
+
+    #[test]
+    fn transparent_source_keeps_destination() {
+        let dst = Rgba::new(0.2, 0.4, 0.6, 0.5);
+        assert_close(over(dst, Rgba::new(1.0, 1.0, 1.0, 0.0)), dst);
+    }
+
+    #[test]
+    fn transparent_destination_takes_source() {
+        let src = Rgba::new(0.2, 0.4, 0.6, 0.5);
+        assert_close(over(Rgba::new(1.0, 1.0, 1.0, 0.0), src), src);
+        assert_close(
+            over(Rgba::TRANSPARENT_BLACK, Rgba::TRANSPARENT_BLACK),
+            Rgba::TRANSPARENT_BLACK,
+        );
+    }
+
+    #[test]
+    fn translucent_colors_mix() {
+        let dst = Rgba::new(0.0, 0.0, 1.0, 0.5);
+        let src = Rgba::new(1.0, 0.0, 0.0, 0.5);
+        // 0.5 of the source and 0.25 of the destination out of 0.75
+        assert_close(over(dst, src), Rgba::new(2.0 / 3.0, 0.0, 1.0 / 3.0, 0.75));
+    }
+
+    #[test]
+    fn erase_lowers_alpha() {
+        let dst = Rgba::new(0.2, 0.4, 0.6, 0.5);
+        assert_close(erase(dst, 0.5), Rgba::new(0.2, 0.4, 0.6, 0.25));
+        assert_close(erase(dst, 1.0), Rgba::TRANSPARENT_BLACK);
+    }
+}
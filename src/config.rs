@@ -40,6 +40,27 @@ pub struct Grid {
 pub struct DefaultBrush {
     pub size: usize,
     pub color: Rgba<f32>,
+    pub secondary_color: Rgba<f32>,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+}
+
+#[derive(Deserialize)]
+pub struct Fill {
+    pub tolerance: f32,
+    pub tolerance_step: f32,
+    pub diagonal: bool,
+    pub bounded: bool,
+    pub fill_transparent: bool,
+    /// How far past what is drawn an unbounded fill can spread
+    pub max_distance: usize,
+}
+
+#[derive(Deserialize)]
+pub struct Gradient {
+    pub radial: bool,
+    pub to_transparent: bool,
+    pub dither: bool,
 }
 
 #[derive(Deserialize)]
@@ -54,17 +75,40 @@ pub struct Status {
     pub width: f32,
 }
 
+#[derive(Deserialize)]
+pub struct Notification {
+    pub duration: f64,
+    pub color: Rgba<f32>,
+}
+
+#[derive(Deserialize)]
+pub struct Render {
+    pub width: usize,
+    pub height: usize,
+    pub transparent_background: bool,
+}
+
+#[derive(Deserialize)]
+pub struct History {
+    pub max_memory_mb: usize,
+}
+
 #[derive(geng::asset::Load, Deserialize)]
 #[load(serde = "toml")]
 pub struct Config {
-    pub max_texture_size: usize,
+    pub opacity_step: f32,
     pub gizmo: Gizmo,
     pub camera: Camera,
     pub default_brush: DefaultBrush,
+    pub fill: Fill,
+    pub gradient: Gradient,
     pub background_color: Rgba<f32>,
     pub wheel: Wheel,
     pub ui: Ui,
     pub grid: Grid,
     pub default_palette: Vec<Rgba<f32>>,
     pub status: Status,
+    pub notification: Notification,
+    pub render: Render,
+    pub history: History,
 }
@@ -241,73 +241,182 @@ impl Ctx {
         );
     }
 
-    pub fn draw_plane(&self, plane: &Plane, framebuffer: &mut ugli::Framebuffer, camera: &Camera) {
-        self.draw_plane_with(plane, framebuffer, camera, &self.shaders.texture, None);
+    /// Draws visible planes, translucent ones last from back to front
+    pub fn draw_planes(&self, model: &Model, framebuffer: &mut ugli::Framebuffer, camera: &Camera) {
+        let camera_pos = (camera.view_matrix().inverse() * vec4(0.0, 0.0, 0.0, 1.0)).into_3d();
+        type Item<'a> = (&'a Plane, mat4<f32>);
+        let (opaque, mut translucent): (Vec<Item>, Vec<Item>) = model
+            .planes
+            .iter()
+            .enumerate()
+            .filter(|(_idx, plane)| plane.visible)
+            .map(|(idx, plane)| (plane, model.world_transform(idx)))
+            .partition(|(plane, _transform)| plane.opacity >= 1.0);
+        translucent.sort_by_key(|(_plane, transform)| {
+            let pos = (*transform * vec4(0.0, 0.0, 0.0, 1.0)).into_3d();
+            std::cmp::Reverse(r32((pos - camera_pos).len()))
+        });
+        for (plane, transform) in opaque.into_iter().chain(translucent) {
+            self.draw_plane(plane, transform, framebuffer, camera);
+        }
+    }
+
+    /// Transform is the world transform of the plane
+    pub fn draw_plane(
+        &self,
+        plane: &Plane,
+        transform: mat4<f32>,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera,
+    ) {
+        self.draw_plane_with(
+            plane,
+            transform,
+            framebuffer,
+            camera,
+            &self.shaders.texture,
+            (plane.opacity < 1.0).then(ugli::BlendMode::straight_alpha),
+        );
     }
 
     pub fn draw_plane_outline(
         &self,
         plane: &Plane,
+        transform: mat4<f32>,
         framebuffer: &mut ugli::Framebuffer,
         camera: &Camera,
     ) {
         self.draw_plane_with(
             plane,
+            transform,
             framebuffer,
             camera,
             &self.shaders.outline,
-            Some(ugli::BlendMode {
-                rgb: ugli::ChannelBlendMode {
-                    src_factor: ugli::BlendFactor::OneMinusDstColor,
-                    dst_factor: ugli::BlendFactor::Zero,
-                    equation: ugli::BlendEquation::Add,
-                },
-                alpha: ugli::ChannelBlendMode {
-                    src_factor: ugli::BlendFactor::One,
-                    dst_factor: ugli::BlendFactor::Zero,
-                    equation: ugli::BlendEquation::Add,
-                },
-            }),
+            Some(inverting_blend_mode()),
         );
     }
 
-    pub fn draw_plane_with(
+    /// Closed line through the points in plane space, drawn over the plane like
+    /// [Ctx::draw_preview] so that it does not need a texture
+    pub fn draw_polygon_outline(
         &self,
-        plane: &Plane,
+        points: &[vec2<f32>],
+        transform: mat4<f32>,
         framebuffer: &mut ugli::Framebuffer,
         camera: &Camera,
-        program: &ugli::Program,
-        blend_mode: Option<ugli::BlendMode>,
     ) {
-        let Some(texture) = &plane.texture.texture else {
-            return;
-        };
         let framebuffer_size = framebuffer.size().map(|x| x as f32);
-        let bb = plane.texture.bounding_box().unwrap().map(|x| x as f32);
-        let transform = plane.transform
-            * mat4::translate(bb.center().extend(0.0))
-            * mat4::scale(bb.size().extend(1.0) / 2.0);
+        let vertices = ugli::VertexBuffer::new_dynamic(
+            self.geng.ugli(),
+            points.iter().map(|&a_pos| QuadVertex { a_pos }).collect(),
+        );
         ugli::draw(
             framebuffer,
-            program,
-            ugli::DrawMode::TriangleFan,
-            &*self.quad,
+            &self.shaders.color_3d,
+            ugli::DrawMode::LineLoop { line_width: 1.0 },
+            &vertices,
             (
                 ugli::uniforms! {
-                 u_texture: texture,
-                 u_texture_size: texture.size(),
-                 u_transform: transform,
-                 u_color: Rgba::WHITE,
+                    u_transform: preview_transform(transform, camera),
+                    u_color: Rgba::WHITE,
                 },
                 camera.uniforms(framebuffer_size),
             ),
             ugli::DrawParameters {
                 depth_func: Some(ugli::DepthFunc::LessOrEqual),
-                blend_mode,
+                blend_mode: Some(inverting_blend_mode()),
                 ..default()
             },
         );
     }
+
+    /// Draws the plane slightly in front of the given world transform, towards the camera,
+    /// only the outline is shown unless filled
+    pub fn draw_preview(
+        &self,
+        plane: &Plane,
+        transform: mat4<f32>,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera,
+        filled: bool,
+    ) {
+        let transform = preview_transform(transform, camera);
+        if filled {
+            self.draw_plane(plane, transform, framebuffer, camera);
+        }
+        self.draw_plane_outline(plane, transform, framebuffer, camera);
+    }
+
+    pub fn draw_plane_with(
+        &self,
+        plane: &Plane,
+        plane_transform: mat4<f32>,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera,
+        program: &ugli::Program,
+        blend_mode: Option<ugli::BlendMode>,
+    ) {
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        for (bb, texture) in plane.texture.borrow().tiles() {
+            let bb = bb.map(|x| x as f32);
+            let transform = plane_transform
+                * mat4::translate(bb.center().extend(0.0))
+                * mat4::scale(bb.size().extend(1.0) / 2.0);
+            ugli::draw(
+                framebuffer,
+                program,
+                ugli::DrawMode::TriangleFan,
+                &*self.quad,
+                (
+                    ugli::uniforms! {
+                     u_texture: texture,
+                     u_texture_size: texture.size(),
+                     u_transform: transform,
+                     u_color: Rgba::new(1.0, 1.0, 1.0, plane.opacity),
+                    },
+                    camera.uniforms(framebuffer_size),
+                ),
+                ugli::DrawParameters {
+                    depth_func: Some(ugli::DepthFunc::LessOrEqual),
+                    blend_mode,
+                    ..default()
+                },
+            );
+        }
+    }
+}
+
+/// Moves the world transform of a plane slightly towards the camera
+fn preview_transform(transform: mat4<f32>, camera: &Camera) -> mat4<f32> {
+    let offset = {
+        const EPS: f32 = 1e-2;
+
+        let forward = (camera.view_matrix().inverse() * vec4(0.0, 0.0, -1.0, 0.0)).xyz();
+        let plane_up = (transform * vec4(0.0, 0.0, 1.0, 0.0)).xyz();
+
+        if vec3::dot(forward, plane_up) < 0.0 {
+            EPS
+        } else {
+            -EPS
+        }
+    };
+    transform * mat4::translate(vec3(0.0, 0.0, offset))
+}
+
+/// Inverts the colors under it, so that outlines show on any background
+fn inverting_blend_mode() -> ugli::BlendMode {
+    ugli::BlendMode {
+        rgb: ugli::ChannelBlendMode {
+            src_factor: ugli::BlendFactor::OneMinusDstColor,
+            dst_factor: ugli::BlendFactor::Zero,
+            equation: ugli::BlendEquation::Add,
+        },
+        alpha: ugli::ChannelBlendMode {
+            src_factor: ugli::BlendFactor::One,
+            dst_factor: ugli::BlendFactor::Zero,
+            equation: ugli::BlendEquation::Add,
+        },
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Ord, Eq)]
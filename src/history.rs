@@ -0,0 +1,727 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneProperties {
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+}
+
+impl PlaneProperties {
+    pub fn of(plane: &Plane) -> Self {
+        Self {
+            visible: plane.visible,
+            locked: plane.locked,
+            opacity: plane.opacity,
+        }
+    }
+
+    fn apply(self, plane: &mut Plane) {
+        plane.visible = self.visible;
+        plane.locked = self.locked;
+        plane.opacity = self.opacity;
+    }
+}
+
+/// A single undoable change to the model
+pub enum Command {
+    Paint {
+        plane: usize,
+        tiles: Vec<TileChange>,
+    },
+    /// Local transforms, relative to the parent
+    Transform {
+        node: Node,
+        old: mat4<f32>,
+        new: mat4<f32>,
+    },
+    /// Local transform is changed along with the parent, so the node can stay in place
+    SetParent {
+        node: Node,
+        old: (Option<usize>, mat4<f32>),
+        new: (Option<usize>, mat4<f32>),
+    },
+    Properties {
+        plane: usize,
+        old: PlaneProperties,
+        new: PlaneProperties,
+    },
+    Rename {
+        plane: usize,
+        old: String,
+        new: String,
+    },
+    /// Plane is removed from one index and inserted at another
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// The plane is only stored here while it is not in the model
+    Create {
+        index: usize,
+        plane: Option<Plane>,
+    },
+    Delete {
+        index: usize,
+        plane: Option<Plane>,
+    },
+    /// Texture is swapped with the one of the plane, so applying it again undoes it
+    Unlink {
+        plane: usize,
+        texture: SharedTexture,
+    },
+    CreateGroup {
+        index: usize,
+        group: Option<Group>,
+    },
+    /// The group has to be empty, children are not moved back when reverted
+    DeleteGroup {
+        index: usize,
+        group: Option<Group>,
+    },
+    /// Applied in order and reverted in reverse order
+    Group(Vec<Command>),
+}
+
+impl Command {
+    /// Creates the paint command for an edit of the plane, if it touched anything
+    pub fn paint(model: &Model, plane: usize, edit: TextureEdit) -> Option<Self> {
+        let tiles = edit.finish(&model.planes.get(plane)?.texture.borrow());
+        if tiles.is_empty() {
+            return None;
+        }
+        Some(Self::Paint { plane, tiles })
+    }
+
+    /// Moves the node into the parent keeping its world transform
+    pub fn set_parent(model: &Model, node: Node, parent: Option<usize>) -> Self {
+        let world = model.node_world_transform(node);
+        Self::SetParent {
+            node,
+            old: (model.node_parent(node), model.node_transform(node)),
+            new: (parent, model.parent_transform(parent).inverse() * world),
+        }
+    }
+
+    /// Single command as is, several as a group, none if empty
+    pub fn group(mut commands: Vec<Command>) -> Option<Self> {
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Self::Group(commands)),
+        }
+    }
+
+    /// Description for the history browser, the model is in the state after the command
+    fn label(&self, model: &Model) -> String {
+        let name = |index: usize| {
+            model.planes.get(index).map_or_else(
+                || format!("plane {index}"),
+                |plane| plane.display_name(index),
+            )
+        };
+        let group_name = |index: usize| {
+            model.groups.get(index).map_or_else(
+                || format!("group {index}"),
+                |group| group.display_name(index),
+            )
+        };
+        let node_name = |node: Node| match node {
+            Node::Plane(index) => name(index),
+            Node::Group(index) => group_name(index),
+        };
+        match self {
+            Self::Paint { plane, .. } => format!("brush stroke on {}", name(*plane)),
+            Self::Transform { node, .. } => format!("transform {}", node_name(*node)),
+            Self::SetParent { node, new, .. } => match new.0 {
+                Some(parent) => format!("move {} into {}", node_name(*node), group_name(parent)),
+                None => format!("move {} out of groups", node_name(*node)),
+            },
+            Self::Properties { plane, old, new } => {
+                let name = name(*plane);
+                if old.visible != new.visible {
+                    format!("{} {name}", if new.visible { "show" } else { "hide" })
+                } else if old.locked != new.locked {
+                    format!("{} {name}", if new.locked { "lock" } else { "unlock" })
+                } else {
+                    format!("{name} opacity {:.0}%", new.opacity * 100.0)
+                }
+            }
+            Self::Rename { old, new, .. } => format!("rename {old:?} to {new:?}"),
+            Self::Move { to, .. } => format!("move {}", name(*to)),
+            Self::Create { index, .. } => format!("create {}", name(*index)),
+            Self::Delete { index, plane } => format!(
+                "delete {}",
+                plane
+                    .as_ref()
+                    .map_or_else(|| name(*index), |plane| plane.display_name(*index)),
+            ),
+            Self::Unlink { plane, .. } => format!("unlink {}", name(*plane)),
+            Self::CreateGroup { index, .. } => format!("create {}", group_name(*index)),
+            Self::DeleteGroup { index, group } => format!(
+                "delete {}",
+                group
+                    .as_ref()
+                    .map_or_else(|| group_name(*index), |group| group.display_name(*index)),
+            ),
+            Self::Group(commands) => match commands.first() {
+                Some(first) => format!("{} and {} more", first.label(model), commands.len() - 1,),
+                None => "nothing".to_owned(),
+            },
+        }
+    }
+
+    /// Approximate number of bytes kept alive by this command,
+    /// not counting the textures that are listed by [Command::textures]
+    fn memory_usage(&self) -> usize {
+        match self {
+            Self::Paint { tiles, .. } => tiles
+                .iter()
+                .flat_map(|tile| [&tile.before, &tile.after])
+                .flatten()
+                .map(|pixels| pixels.data.len())
+                .sum(),
+            Self::Transform { .. }
+            | Self::SetParent { .. }
+            | Self::Properties { .. }
+            | Self::Rename { .. }
+            | Self::Move { .. }
+            | Self::Create { .. }
+            | Self::Delete { .. }
+            | Self::Unlink { .. }
+            | Self::CreateGroup { .. }
+            | Self::DeleteGroup { .. } => 0,
+            Self::Group(commands) => commands.iter().map(Command::memory_usage).sum(),
+        }
+    }
+
+    /// Textures kept alive by the command, they can be shared with other commands
+    fn textures(&self, f: &mut dyn FnMut(&SharedTexture)) {
+        match self {
+            Self::Create { plane, .. } | Self::Delete { plane, .. } => {
+                if let Some(plane) = plane {
+                    f(&plane.texture);
+                }
+            }
+            Self::Unlink { texture, .. } => f(texture),
+            Self::Group(commands) => {
+                for command in commands {
+                    command.textures(f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn take(&mut self, step: Step, model: &mut Model) {
+        match step {
+            Step::Undo => self.revert(model),
+            Step::Redo => self.apply(model),
+        }
+    }
+
+    /// Only for building up commands, history has to record it afterwards
+    pub fn apply(&mut self, model: &mut Model) {
+        match self {
+            Self::Paint { plane, tiles } => {
+                let mut texture = model.planes[*plane].texture.borrow_mut();
+                for tile in tiles {
+                    texture.set_tile(tile.index, tile.after.as_ref());
+                }
+            }
+            Self::Transform { node, new, .. } => model.set_node_transform(*node, *new),
+            Self::SetParent { node, new, .. } => {
+                model.set_node_parent(*node, new.0);
+                model.set_node_transform(*node, new.1);
+            }
+            Self::Properties { plane, new, .. } => new.apply(&mut model.planes[*plane]),
+            Self::Rename { plane, new, .. } => model.planes[*plane].name = new.clone(),
+            Self::Move { from, to } => model.move_plane(*from, *to),
+            Self::Create { index, plane } => model.insert_plane(*index, plane.take().unwrap()),
+            Self::Delete { index, plane } => *plane = Some(model.remove_plane(*index)),
+            Self::Unlink { plane, texture } => {
+                std::mem::swap(&mut model.planes[*plane].texture, texture)
+            }
+            Self::CreateGroup { index, group } => model.insert_group(*index, group.take().unwrap()),
+            Self::DeleteGroup { index, group } => *group = Some(model.remove_group(*index)),
+            Self::Group(commands) => {
+                for command in commands {
+                    command.apply(model);
+                }
+            }
+        }
+    }
+
+    fn revert(&mut self, model: &mut Model) {
+        match self {
+            Self::Paint { plane, tiles } => {
+                let mut texture = model.planes[*plane].texture.borrow_mut();
+                for tile in tiles {
+                    texture.set_tile(tile.index, tile.before.as_ref());
+                }
+            }
+            Self::Transform { node, old, .. } => model.set_node_transform(*node, *old),
+            Self::SetParent { node, old, .. } => {
+                model.set_node_parent(*node, old.0);
+                model.set_node_transform(*node, old.1);
+            }
+            Self::Properties { plane, old, .. } => old.apply(&mut model.planes[*plane]),
+            Self::Rename { plane, old, .. } => model.planes[*plane].name = old.clone(),
+            Self::Move { from, to } => model.move_plane(*to, *from),
+            Self::Create { index, plane } => *plane = Some(model.remove_plane(*index)),
+            Self::Delete { index, plane } => model.insert_plane(*index, plane.take().unwrap()),
+            Self::Unlink { plane, texture } => {
+                std::mem::swap(&mut model.planes[*plane].texture, texture)
+            }
+            Self::CreateGroup { index, group } => *group = Some(model.remove_group(*index)),
+            Self::DeleteGroup { index, group } => model.insert_group(*index, group.take().unwrap()),
+            Self::Group(commands) => {
+                for command in commands.iter_mut().rev() {
+                    command.revert(model);
+                }
+            }
+        }
+    }
+}
+
+struct TreeNode {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Child that redo goes to, the most recently visited one
+    redo_child: Option<usize>,
+    /// Takes the parent state to this one, the root has none
+    command: Option<Command>,
+    label: String,
+}
+
+/// Row of the history browser
+pub struct Entry {
+    pub id: usize,
+    pub depth: usize,
+    pub label: String,
+    pub current: bool,
+    /// Whether the entry is the current state or one of the states before it
+    pub active: bool,
+}
+
+/// Memory kept alive by the recorded commands
+#[derive(Default)]
+struct MemoryUsage {
+    total: usize,
+    /// How many times each texture is held and how much it was counted with,
+    /// so that shared textures are only counted once
+    textures: HashMap<*const RefCell<Texture>, (usize, usize)>,
+}
+
+impl MemoryUsage {
+    fn add(&mut self, command: &Command) {
+        self.total += command.memory_usage();
+        command.textures(&mut |texture| {
+            let (count, memory) = self
+                .textures
+                .entry(Rc::as_ptr(texture))
+                .or_insert_with(|| (0, texture.borrow().memory_usage()));
+            if *count == 0 {
+                self.total += *memory;
+            }
+            *count += 1;
+        });
+    }
+
+    fn remove(&mut self, command: &Command) {
+        self.total = self.total.saturating_sub(command.memory_usage());
+        command.textures(&mut |texture| {
+            let key = Rc::as_ptr(texture);
+            let Some((count, memory)) = self.textures.get_mut(&key) else {
+                return;
+            };
+            *count -= 1;
+            if *count == 0 {
+                self.total = self.total.saturating_sub(*memory);
+                self.textures.remove(&key);
+            }
+        });
+    }
+}
+
+/// Tree of commands that keeps abandoned branches around,
+/// the current node is the state the model is in
+pub struct History {
+    max_memory: usize,
+    /// Kept up to date as the nodes change
+    ///
+    /// Approximate, since textures of removed planes can still grow through linked ones
+    memory_usage: MemoryUsage,
+    nodes: BTreeMap<usize, TreeNode>,
+    next_id: usize,
+    root: usize,
+    current: usize,
+}
+
+/// Which way a command is taken when moving through the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Undo,
+    Redo,
+}
+
+impl History {
+    pub fn new(ctx: &Ctx) -> Self {
+        Self::with_max_memory(ctx.config.history.max_memory_mb * 1024 * 1024)
+    }
+
+    fn with_max_memory(max_memory: usize) -> Self {
+        let mut result = Self {
+            max_memory,
+            memory_usage: default(),
+            nodes: BTreeMap::new(),
+            next_id: 0,
+            root: 0,
+            current: 0,
+        };
+        result.clear("new model");
+        result
+    }
+
+    /// Records a command, the change itself must already be done
+    ///
+    /// Oldest branches are forgotten when over the memory limit
+    pub fn push(&mut self, model: &Model, command: Command) {
+        let label = command.label(model);
+        self.push_labeled(command, label);
+    }
+
+    fn push_labeled(&mut self, command: Command, label: String) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.memory_usage.add(&command);
+        self.nodes.insert(
+            id,
+            TreeNode {
+                parent: Some(self.current),
+                children: vec![],
+                redo_child: None,
+                command: Some(command),
+                label,
+            },
+        );
+        let parent = self.nodes.get_mut(&self.current).unwrap();
+        parent.children.push(id);
+        parent.redo_child = Some(id);
+        self.current = id;
+        self.forget();
+    }
+
+    /// Applies the command and records it
+    pub fn apply(&mut self, model: &mut Model, mut command: Command) {
+        command.apply(model);
+        self.push(model, command);
+    }
+
+    pub fn undo(&mut self, model: &mut Model) {
+        self.step(Step::Undo, &mut |command, step| command.take(step, model));
+    }
+
+    pub fn redo(&mut self, model: &mut Model) {
+        self.step(Step::Redo, &mut |command, step| command.take(step, model));
+    }
+
+    /// Undoes and redoes commands until the model is in the state of the given node
+    pub fn jump(&mut self, model: &mut Model, target: usize) {
+        self.jump_with(target, &mut |command, step| command.take(step, model));
+    }
+
+    /// Moves to the parent or to the redo child, the tree itself does not need the model
+    fn step(&mut self, step: Step, take: &mut dyn FnMut(&mut Command, Step)) {
+        // Node of the command and the one that becomes current
+        let (id, next) = match step {
+            Step::Undo => {
+                let Some(parent) = self.nodes[&self.current].parent else {
+                    return;
+                };
+                (self.current, parent)
+            }
+            Step::Redo => {
+                let Some(child) = self.nodes[&self.current].redo_child else {
+                    return;
+                };
+                (child, child)
+            }
+        };
+        let command = self.nodes.get_mut(&id).unwrap().command.as_mut().unwrap();
+        // Commands keep different things alive before and after, like a deleted plane
+        self.memory_usage.remove(command);
+        take(command, step);
+        self.memory_usage.add(command);
+        if step == Step::Undo {
+            self.nodes.get_mut(&next).unwrap().redo_child = Some(self.current);
+        }
+        self.current = next;
+    }
+
+    fn jump_with(&mut self, target: usize, take: &mut dyn FnMut(&mut Command, Step)) {
+        if !self.nodes.contains_key(&target) {
+            return;
+        }
+        let path = self.path(target);
+        while !path.contains(&self.current) {
+            self.step(Step::Undo, take);
+        }
+        for &id in path
+            .iter()
+            .rev()
+            .skip_while(|&&id| id != self.current)
+            .skip(1)
+        {
+            self.nodes.get_mut(&self.current).unwrap().redo_child = Some(id);
+            self.step(Step::Redo, take);
+        }
+    }
+
+    /// Forgets everything and starts a new tree
+    pub fn clear(&mut self, label: impl Into<String>) {
+        self.nodes.clear();
+        self.memory_usage = default();
+        self.root = self.next_id;
+        self.current = self.root;
+        self.next_id += 1;
+        self.nodes.insert(
+            self.root,
+            TreeNode {
+                parent: None,
+                children: vec![],
+                redo_child: None,
+                command: None,
+                label: label.into(),
+            },
+        );
+    }
+
+    /// Node and all of its ancestors, starting from the node itself
+    fn path(&self, mut id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        while let Some(parent) = self.nodes[&id].parent {
+            path.push(parent);
+            id = parent;
+        }
+        path
+    }
+
+    /// Every node in depth first order, later branches are indented
+    pub fn entries(&self) -> Vec<Entry> {
+        let active = self.path(self.current);
+        let mut result = Vec::new();
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, depth)) = stack.pop() {
+            let node = &self.nodes[&id];
+            result.push(Entry {
+                id,
+                depth,
+                label: node.label.clone(),
+                current: id == self.current,
+                active: active.contains(&id),
+            });
+            for (index, &child) in node.children.iter().enumerate().rev() {
+                stack.push((child, if index == 0 { depth } else { depth + 1 }));
+            }
+        }
+        result
+    }
+
+    fn forget(&mut self) {
+        // Forgetting leaves does not change the path, dropped roots are not looked up again
+        let active = self.path(self.current);
+        while self.memory_usage.total > self.max_memory {
+            let oldest_leaf = self
+                .nodes
+                .iter()
+                .find(|(id, node)| node.children.is_empty() && !active.contains(id))
+                .map(|(&id, _node)| id);
+            if let Some(id) = oldest_leaf {
+                let node = self.nodes.remove(&id).unwrap();
+                if let Some(command) = &node.command {
+                    self.memory_usage.remove(command);
+                }
+                let parent = self.nodes.get_mut(&node.parent.unwrap()).unwrap();
+                parent.children.retain(|&child| child != id);
+                if parent.redo_child == Some(id) {
+                    parent.redo_child = parent.children.last().copied();
+                }
+                continue;
+            }
+            // Only the states leading to the current one are left, so the oldest can be dropped
+            // as long as the last command stays undoable
+            if self.nodes[&self.current]
+                .parent
+                .map_or(true, |parent| parent == self.root)
+            {
+                break;
+            }
+            let root = self.nodes.remove(&self.root).unwrap();
+            self.root = root.children[0];
+            let new_root = self.nodes.get_mut(&self.root).unwrap();
+            new_root.parent = None;
+            if let Some(command) = new_root.command.take() {
+                self.memory_usage.remove(&command);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Never applied, only tells the commands apart
+    fn marker(name: &str) -> Command {
+        Command::Rename {
+            plane: 0,
+            old: String::new(),
+            new: name.to_owned(),
+        }
+    }
+
+    /// Keeps 8 bytes alive
+    fn paint() -> Command {
+        Command::Paint {
+            plane: 0,
+            tiles: vec![TileChange {
+                index: vec2::ZERO,
+                before: Some(document::Pixels::new(vec2(1, 1))),
+                after: Some(document::Pixels::new(vec2(1, 1))),
+            }],
+        }
+    }
+
+    fn push(history: &mut History, name: &str) {
+        history.push_labeled(marker(name), name.to_owned());
+    }
+
+    fn name(command: &Command) -> String {
+        match command {
+            Command::Rename { new, .. } => new.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Commands taken by the move, in order
+    fn take_log(
+        history: &mut History,
+        f: impl FnOnce(&mut History, &mut dyn FnMut(&mut Command, Step)),
+    ) -> Vec<(String, Step)> {
+        let mut log = Vec::new();
+        f(history, &mut |command, step| {
+            log.push((name(command), step))
+        });
+        log
+    }
+
+    fn id_of(history: &History, label: &str) -> usize {
+        history
+            .entries()
+            .into_iter()
+            .find(|entry| entry.label == label)
+            .unwrap()
+            .id
+    }
+
+    fn labels(history: &History) -> Vec<(String, usize)> {
+        history
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.label, entry.depth))
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_current_branch() {
+        let mut history = History::with_max_memory(usize::MAX);
+        push(&mut history, "a");
+        push(&mut history, "b");
+        let undo = |history: &mut History, take: &mut dyn FnMut(&mut Command, Step)| {
+            history.step(Step::Undo, take)
+        };
+        let redo = |history: &mut History, take: &mut dyn FnMut(&mut Command, Step)| {
+            history.step(Step::Redo, take)
+        };
+        assert_eq!(take_log(&mut history, undo), [("b".to_owned(), Step::Undo)]);
+        assert_eq!(take_log(&mut history, redo), [("b".to_owned(), Step::Redo)]);
+        assert!(take_log(&mut history, redo).is_empty());
+        take_log(&mut history, undo);
+        assert_eq!(take_log(&mut history, undo), [("a".to_owned(), Step::Undo)]);
+        // Nothing before the root
+        assert!(take_log(&mut history, undo).is_empty());
+    }
+
+    #[test]
+    fn new_commands_after_undo_start_a_branch() {
+        let mut history = History::with_max_memory(usize::MAX);
+        push(&mut history, "a");
+        push(&mut history, "b");
+        history.step(Step::Undo, &mut |_command, _step| {});
+        push(&mut history, "c");
+        assert_eq!(
+            labels(&history),
+            [
+                ("new model".to_owned(), 0),
+                ("a".to_owned(), 0),
+                ("b".to_owned(), 0),
+                ("c".to_owned(), 1),
+            ]
+        );
+        let active: Vec<bool> = history.entries().iter().map(|entry| entry.active).collect();
+        assert_eq!(active, [true, true, false, true]);
+
+        let b = id_of(&history, "b");
+        let log = take_log(&mut history, |history, take| history.jump_with(b, take));
+        assert_eq!(
+            log,
+            [("c".to_owned(), Step::Undo), ("b".to_owned(), Step::Redo)]
+        );
+        let current: Vec<bool> = history
+            .entries()
+            .iter()
+            .map(|entry| entry.current)
+            .collect();
+        assert_eq!(current, [false, false, true, false]);
+    }
+
+    #[test]
+    fn redo_goes_to_the_last_visited_branch() {
+        let mut history = History::with_max_memory(usize::MAX);
+        push(&mut history, "a");
+        history.step(Step::Undo, &mut |_command, _step| {});
+        push(&mut history, "b");
+        let a = id_of(&history, "a");
+        history.jump_with(a, &mut |_command, _step| {});
+        history.step(Step::Undo, &mut |_command, _step| {});
+        let log = take_log(&mut history, |history, take| history.step(Step::Redo, take));
+        assert_eq!(log, [("a".to_owned(), Step::Redo)]);
+    }
+
+    #[test]
+    fn forgets_abandoned_branches_first() {
+        let mut history = History::with_max_memory(24);
+        history.push_labeled(paint(), "p1".to_owned());
+        history.push_labeled(paint(), "p2".to_owned());
+        history.step(Step::Undo, &mut |_command, _step| {});
+        history.push_labeled(paint(), "p3".to_owned());
+        history.push_labeled(paint(), "p4".to_owned());
+        let remaining: Vec<String> = labels(&history)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(remaining, ["new model", "p1", "p3", "p4"]);
+
+        // Then the oldest states, as long as the last command can be undone
+        history.push_labeled(paint(), "p5".to_owned());
+        let remaining: Vec<String> = labels(&history)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(remaining, ["p1", "p3", "p4", "p5"]);
+        assert_eq!(history.memory_usage.total, 24);
+    }
+}
@@ -0,0 +1,121 @@
+use super::*;
+
+const ROW_HEIGHT: f32 = 1.0;
+const INDENT: f32 = 1.0;
+const SCROLL_SPEED: f32 = 0.01;
+
+/// Overlay listing every state in the history tree, clicking one jumps to it
+pub struct Browser {
+    ctx: Ctx,
+    camera: Camera2d,
+    scroll: f32,
+}
+
+impl Browser {
+    pub fn new(ctx: &Ctx) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            camera: Camera2d {
+                center: vec2::ZERO,
+                rotation: Angle::ZERO,
+                fov: 20.0,
+            },
+            scroll: 0.0,
+        }
+    }
+
+    /// Left center point of the row
+    fn row_pos(&self, framebuffer_size: vec2<f32>, row: usize) -> vec2<f32> {
+        let half_size = vec2(
+            self.camera.fov / 2.0 * framebuffer_size.aspect(),
+            self.camera.fov / 2.0,
+        );
+        vec2(
+            -half_size.x + INDENT,
+            half_size.y - ROW_HEIGHT * (row as f32 + 1.0) + self.scroll,
+        )
+    }
+
+    fn hovered_row(&self, row_count: usize) -> Option<usize> {
+        let framebuffer_size = self.ctx.geng.window().size().map(|x| x as f32);
+        let cursor_pos = self.camera.screen_to_world(
+            framebuffer_size,
+            self.ctx.geng.window().cursor_position()?.map(|x| x as f32),
+        );
+        let top = self.row_pos(framebuffer_size, 0).y + ROW_HEIGHT / 2.0;
+        let row = ((top - cursor_pos.y) / ROW_HEIGHT).floor();
+        (row >= 0.0 && (row as usize) < row_count).then_some(row as usize)
+    }
+
+    /// Returns true if the model was changed
+    pub fn handle_event(&mut self, event: &geng::Event, state: &mut State) -> bool {
+        match *event {
+            geng::Event::MousePress {
+                button: geng::MouseButton::Left,
+            } => {
+                let entries = state.history.entries();
+                if let Some(row) = self.hovered_row(entries.len()) {
+                    state.put_down_pixels();
+                    state.history.jump(&mut state.model, entries[row].id);
+                    return true;
+                }
+            }
+            geng::Event::Wheel { delta } => {
+                self.scroll = (self.scroll - delta as f32 * SCROLL_SPEED).max(0.0);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn draw(&mut self, framebuffer: &mut ugli::Framebuffer, state: &State) {
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let entries = state.history.entries();
+        let hovered = self.hovered_row(entries.len());
+        let font = self.ctx.geng.default_font();
+        let half_height = self.camera.fov / 2.0;
+        let left = self.row_pos(framebuffer_size, 0).x - INDENT / 2.0;
+        self.ctx.geng.draw2d().draw2d(
+            framebuffer,
+            &self.camera,
+            &draw2d::Quad::new(
+                Aabb2 {
+                    min: vec2(left - INDENT / 2.0, -half_height),
+                    max: vec2(left + self.camera.fov, half_height),
+                },
+                Rgba::new(0.0, 0.0, 0.0, 0.5),
+            ),
+        );
+        for (row, entry) in entries.iter().enumerate() {
+            let pos = self.row_pos(framebuffer_size, row);
+            if hovered == Some(row) || entry.current {
+                self.ctx.geng.draw2d().draw2d(
+                    framebuffer,
+                    &self.camera,
+                    &draw2d::Quad::new(
+                        Aabb2::point(pos)
+                            .extend_left(INDENT / 2.0)
+                            .extend_right(self.camera.fov)
+                            .extend_symmetric(vec2(0.0, ROW_HEIGHT / 2.0)),
+                        Rgba::new(1.0, 1.0, 1.0, if entry.current { 0.2 } else { 0.1 }),
+                    ),
+                );
+            }
+            font.draw(
+                framebuffer,
+                &self.camera,
+                &entry.label,
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::CENTER),
+                mat3::translate(pos + vec2(entry.depth as f32 * INDENT, 0.0))
+                    * mat3::scale_uniform(ROW_HEIGHT * 0.8),
+                if entry.current {
+                    Rgba::YELLOW
+                } else if entry.active {
+                    Rgba::WHITE
+                } else {
+                    Rgba::GRAY
+                },
+            );
+        }
+    }
+}
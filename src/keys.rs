@@ -19,6 +19,13 @@ pub struct ToolKeys {
     pub pick: Option<KeyBind>,
     pub create: Option<KeyBind>,
     pub color_picker: Option<KeyBind>,
+    pub fill: Option<KeyBind>,
+    pub line: Option<KeyBind>,
+    pub rectangle: Option<KeyBind>,
+    pub ellipse: Option<KeyBind>,
+    pub select: Option<KeyBind>,
+    pub lasso: Option<KeyBind>,
+    pub gradient: Option<KeyBind>,
 }
 
 #[derive(Deserialize)]
@@ -34,27 +41,105 @@ pub struct Precision {
     pub unbounded: geng::Key,
 }
 
+#[derive(Deserialize)]
+pub struct Layers {
+    pub toggle: KeyBind,
+    pub rename: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct Selection {
+    pub toggle: geng::Key,
+}
+
 #[derive(Deserialize)]
 pub struct Brush {
     pub increase_size: KeyBind,
     pub decrease_size: KeyBind,
+    pub increase_opacity: KeyBind,
+    pub decrease_opacity: KeyBind,
+    pub blend_mode: KeyBind,
     pub sizes: Vec<KeyBind>,
 }
 
+#[derive(Deserialize)]
+pub struct Fill {
+    pub increase_tolerance: KeyBind,
+    pub decrease_tolerance: KeyBind,
+    pub connectivity: KeyBind,
+    pub bounded: KeyBind,
+    pub transparent: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct Gradient {
+    pub radial: KeyBind,
+    pub transparent: KeyBind,
+    pub dither: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct Shape {
+    pub filled: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct PixelSelectionKeys {
+    pub copy: KeyBind,
+    pub cut: KeyBind,
+    pub paste: KeyBind,
+    /// Puts the floating pixels down
+    pub commit: KeyBind,
+    pub deselect: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct PlaneKeys {
+    pub toggle_visible: KeyBind,
+    pub toggle_locked: KeyBind,
+    pub increase_opacity: KeyBind,
+    pub decrease_opacity: KeyBind,
+}
+
+#[derive(Deserialize)]
+pub struct GroupKeys {
+    pub create: KeyBind,
+    pub ungroup: KeyBind,
+    pub select_parent: KeyBind,
+}
+
 #[derive(Deserialize)]
 pub struct Config {
     pub save: KeyBind,
     pub load: KeyBind,
+    pub import_image: KeyBind,
+    pub export_gltf: KeyBind,
+    pub export_render: KeyBind,
+    pub trim: KeyBind,
     pub switch_plane: KeyBind,
     pub palette: KeyBind,
+    pub add_to_palette: KeyBind,
     pub first_person: KeyBind,
     pub camera: Camera,
     pub tools: Tools,
     pub precision: Precision,
+    pub selection: Selection,
+    pub layers: Layers,
     pub brush: Brush,
+    pub fill: Fill,
+    pub shape: Shape,
+    pub gradient: Gradient,
     pub color_chooser: KeyBind,
+    pub swap_colors: KeyBind,
     pub undo: KeyBind,
+    pub history_browser: KeyBind,
     pub redo: KeyBind,
     pub show_origin: geng::Key,
     pub delete_plane: KeyBind,
+    pub duplicate: KeyBind,
+    pub duplicate_linked: KeyBind,
+    pub unlink: KeyBind,
+    pub plane: PlaneKeys,
+    pub group: GroupKeys,
+    pub pixel_selection: PixelSelectionKeys,
 }
@@ -0,0 +1,378 @@
+use super::*;
+
+const ROW_HEIGHT: f32 = 1.5;
+const THUMBNAIL_SIZE: f32 = 1.2;
+const BUTTON_WIDTH: f32 = 1.0;
+const WIDTH: f32 = 9.0;
+/// Planes inside of groups are shifted right by this much per group
+const INDENT: f32 = 0.5;
+const SCROLL_SPEED: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Button {
+    Visible,
+    Locked,
+    Delete,
+}
+
+impl Button {
+    const ALL: [Self; 3] = [Self::Visible, Self::Locked, Self::Delete];
+
+    fn text(self, plane: &Plane) -> &'static str {
+        match self {
+            Self::Visible if plane.visible => "o",
+            Self::Visible => "-",
+            Self::Locked if plane.locked => "L",
+            Self::Locked => "-",
+            Self::Delete => "x",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hover {
+    Row(usize),
+    Button(usize, Button),
+}
+
+struct Rename {
+    plane: usize,
+    text: String,
+}
+
+/// Side panel listing every plane of the model
+pub struct Panel {
+    ctx: Ctx,
+    camera: Camera2d,
+    scroll: f32,
+    /// Row that the mouse was pressed on, it is moved when released over another row
+    drag: Option<usize>,
+    rename: Option<Rename>,
+}
+
+impl Panel {
+    pub fn new(ctx: &Ctx) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            camera: Camera2d {
+                center: vec2::ZERO,
+                rotation: Angle::ZERO,
+                fov: 20.0,
+            },
+            scroll: 0.0,
+            drag: None,
+            rename: None,
+        }
+    }
+
+    fn area(&self, framebuffer_size: vec2<f32>) -> Aabb2<f32> {
+        let half_size = vec2(
+            self.camera.fov / 2.0 * framebuffer_size.aspect(),
+            self.camera.fov / 2.0,
+        );
+        Aabb2 {
+            min: vec2(half_size.x - WIDTH, -half_size.y),
+            max: half_size,
+        }
+    }
+
+    fn row_area(&self, framebuffer_size: vec2<f32>, row: usize) -> Aabb2<f32> {
+        let area = self.area(framebuffer_size);
+        let top = area.max.y - ROW_HEIGHT * row as f32 + self.scroll;
+        Aabb2 {
+            min: vec2(area.min.x, top - ROW_HEIGHT),
+            max: vec2(area.max.x, top),
+        }
+    }
+
+    fn button_area(&self, row_area: Aabb2<f32>, button: Button) -> Aabb2<f32> {
+        let index = Button::ALL.iter().position(|&b| b == button).unwrap();
+        let right = row_area.max.x - BUTTON_WIDTH * (Button::ALL.len() - 1 - index) as f32;
+        Aabb2 {
+            min: vec2(right - BUTTON_WIDTH, row_area.min.y),
+            max: vec2(right, row_area.max.y),
+        }
+    }
+
+    fn cursor_pos(&self) -> Option<vec2<f32>> {
+        let framebuffer_size = self.ctx.geng.window().size().map(|x| x as f32);
+        Some(self.camera.screen_to_world(
+            framebuffer_size,
+            self.ctx.geng.window().cursor_position()?.map(|x| x as f32),
+        ))
+    }
+
+    fn is_hovered(&self) -> bool {
+        let framebuffer_size = self.ctx.geng.window().size().map(|x| x as f32);
+        self.cursor_pos()
+            .map_or(false, |pos| self.area(framebuffer_size).contains(pos))
+    }
+
+    fn hover(&self, plane_count: usize) -> Option<Hover> {
+        let framebuffer_size = self.ctx.geng.window().size().map(|x| x as f32);
+        let pos = self.cursor_pos()?;
+        if !self.area(framebuffer_size).contains(pos) {
+            return None;
+        }
+        (0..plane_count).find_map(|row| {
+            let row_area = self.row_area(framebuffer_size, row);
+            if !row_area.contains(pos) {
+                return None;
+            }
+            Some(
+                Button::ALL
+                    .into_iter()
+                    .find(|&button| self.button_area(row_area, button).contains(pos))
+                    .map_or(Hover::Row(row), |button| Hover::Button(row, button)),
+            )
+        })
+    }
+
+    fn start_rename(&mut self, state: &State) {
+        let Some(plane) = state.selected.active() else {
+            return;
+        };
+        let text = state.model.planes[plane].name.clone();
+        self.ctx.geng.window().start_text_edit(&text);
+        self.rename = Some(Rename { plane, text });
+    }
+
+    fn finish_rename(&mut self, state: &mut State, commit: bool) {
+        let Some(rename) = self.rename.take() else {
+            return;
+        };
+        self.ctx.geng.window().stop_text_edit();
+        let Some(plane) = state.model.planes.get(rename.plane) else {
+            return;
+        };
+        if commit && plane.name != rename.text {
+            let command = Command::Rename {
+                plane: rename.plane,
+                old: plane.name.clone(),
+                new: rename.text,
+            };
+            state.history.apply(&mut state.model, command);
+        }
+    }
+
+    pub fn close(&mut self, state: &mut State) {
+        self.finish_rename(state, false);
+    }
+
+    fn click(&mut self, hover: Hover, state: &mut State) {
+        match hover {
+            Hover::Row(row) => {
+                if self
+                    .ctx
+                    .geng
+                    .window()
+                    .is_key_pressed(self.ctx.keys.selection.toggle)
+                {
+                    state.selected.toggle(row);
+                } else {
+                    state.selected = Selection::single(row);
+                }
+            }
+            Hover::Button(row, button) => {
+                let plane = &state.model.planes[row];
+                let old = history::PlaneProperties::of(plane);
+                let mut new = old;
+                let command = match button {
+                    Button::Visible => {
+                        new.visible = !new.visible;
+                        Command::Properties {
+                            plane: row,
+                            old,
+                            new,
+                        }
+                    }
+                    Button::Locked => {
+                        new.locked = !new.locked;
+                        Command::Properties {
+                            plane: row,
+                            old,
+                            new,
+                        }
+                    }
+                    Button::Delete => {
+                        if plane.locked {
+                            return;
+                        }
+                        state.commit_floating();
+                        state.selected.removed(row);
+                        state.pixels_plane_removed(row);
+                        Command::Delete {
+                            index: row,
+                            plane: None,
+                        }
+                    }
+                };
+                state.history.apply(&mut state.model, command);
+            }
+        }
+    }
+
+    /// Returns true if the event was used by the panel
+    pub fn handle_event(&mut self, event: &geng::Event, state: &mut State) -> bool {
+        if self.rename.is_some() {
+            match event {
+                geng::Event::EditText(text) => {
+                    if let Some(rename) = &mut self.rename {
+                        rename.text = text.clone();
+                    }
+                }
+                geng::Event::KeyPress {
+                    key: geng::Key::Enter,
+                } => self.finish_rename(state, true),
+                geng::Event::KeyPress {
+                    key: geng::Key::Escape,
+                } => self.finish_rename(state, false),
+                geng::Event::MousePress { .. } => self.finish_rename(state, true),
+                _ => {}
+            }
+            return !matches!(event, geng::Event::Draw);
+        }
+        if self.ctx.keys.layers.rename.matches(event, &self.ctx) {
+            self.start_rename(state);
+            return true;
+        }
+        match *event {
+            geng::Event::MousePress {
+                button: geng::MouseButton::Left,
+            } if self.is_hovered() => {
+                match self.hover(state.model.planes.len()) {
+                    Some(Hover::Row(row)) => self.drag = Some(row),
+                    Some(hover) => self.click(hover, state),
+                    None => {}
+                }
+                true
+            }
+            geng::Event::MouseRelease {
+                button: geng::MouseButton::Left,
+            } if self.drag.is_some() => {
+                let from = self.drag.take().unwrap();
+                match self.hover(state.model.planes.len()) {
+                    Some(Hover::Row(to) | Hover::Button(to, _)) if to != from => {
+                        state
+                            .history
+                            .apply(&mut state.model, Command::Move { from, to });
+                        state.selected.moved(from, to);
+                        state.pixels_plane_moved(from, to);
+                    }
+                    _ => self.click(Hover::Row(from), state),
+                }
+                true
+            }
+            geng::Event::Wheel { delta } if self.is_hovered() => {
+                self.scroll = (self.scroll - delta as f32 * SCROLL_SPEED).max(0.0);
+                true
+            }
+            // Releases are left alone so that strokes started outside the panel still end
+            geng::Event::MousePress { .. } => self.is_hovered(),
+            _ => false,
+        }
+    }
+
+    fn draw_thumbnail(&self, framebuffer: &mut ugli::Framebuffer, plane: &Plane, area: Aabb2<f32>) {
+        let texture = plane.texture.borrow();
+        let Some(bounds) = texture.allocated_bounds() else {
+            return;
+        };
+        let bounds = bounds.map(|x| x as f32);
+        let scale = area.width().min(area.height()) / bounds.width().max(bounds.height());
+        for (bb, texture) in texture.tiles() {
+            let bb = bb
+                .map(|x| x as f32)
+                .map_bounds(|p| area.center() + (p - bounds.center()) * scale);
+            self.ctx.geng.draw2d().draw2d(
+                framebuffer,
+                &self.camera,
+                &draw2d::TexturedQuad::new(bb, texture),
+            );
+        }
+    }
+
+    pub fn draw(&mut self, framebuffer: &mut ugli::Framebuffer, state: &State) {
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        let font = self.ctx.geng.default_font();
+        let hover = self.hover(state.model.planes.len());
+        let hovered_row = match hover {
+            Some(Hover::Row(row) | Hover::Button(row, _)) => Some(row),
+            None => None,
+        };
+        self.ctx.geng.draw2d().draw2d(
+            framebuffer,
+            &self.camera,
+            &draw2d::Quad::new(self.area(framebuffer_size), Rgba::new(0.0, 0.0, 0.0, 0.5)),
+        );
+        for (row, plane) in state.model.planes.iter().enumerate() {
+            let row_area = self.row_area(framebuffer_size, row);
+            let highlight = if self.drag.is_some() && hovered_row == Some(row) {
+                Some(0.3)
+            } else if state.selected.contains(row) {
+                Some(0.2)
+            } else if hover == Some(Hover::Row(row)) {
+                Some(0.1)
+            } else {
+                None
+            };
+            if let Some(alpha) = highlight {
+                self.ctx.geng.draw2d().draw2d(
+                    framebuffer,
+                    &self.camera,
+                    &draw2d::Quad::new(row_area, Rgba::new(1.0, 1.0, 1.0, alpha)),
+                );
+            }
+
+            let indent = INDENT * state.model.depth(Node::Plane(row)) as f32;
+            let thumbnail_center = vec2(
+                row_area.min.x + indent + ROW_HEIGHT / 2.0,
+                row_area.center().y,
+            );
+            self.draw_thumbnail(
+                framebuffer,
+                plane,
+                Aabb2::point(thumbnail_center).extend_uniform(THUMBNAIL_SIZE / 2.0),
+            );
+
+            let name = match &self.rename {
+                Some(rename) if rename.plane == row => format!("{}_", rename.text),
+                _ if state.model.linked_planes(row).next().is_some() => {
+                    format!("{} (linked)", plane.display_name(row))
+                }
+                _ => plane.display_name(row),
+            };
+            font.draw(
+                framebuffer,
+                &self.camera,
+                &name,
+                vec2(geng::TextAlign::LEFT, geng::TextAlign::CENTER),
+                mat3::translate(vec2(
+                    row_area.min.x + indent + ROW_HEIGHT,
+                    row_area.center().y,
+                )) * mat3::scale_uniform(0.8),
+                if plane.visible {
+                    Rgba::WHITE
+                } else {
+                    Rgba::GRAY
+                },
+            );
+
+            for button in Button::ALL {
+                let area = self.button_area(row_area, button);
+                font.draw(
+                    framebuffer,
+                    &self.camera,
+                    button.text(plane),
+                    vec2::splat(geng::TextAlign::CENTER),
+                    mat3::translate(area.center()) * mat3::scale_uniform(0.8),
+                    if hover == Some(Hover::Button(row, button)) {
+                        Rgba::YELLOW
+                    } else {
+                        Rgba::WHITE
+                    },
+                );
+            }
+        }
+    }
+}
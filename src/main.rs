@@ -4,47 +4,68 @@ use geng::prelude::*;
 
 use pog_paint::*;
 
+use pog_paint::document;
+
+mod blend;
 mod camera;
 mod color;
 mod config;
 mod ctx;
 mod gizmo;
+mod history;
+mod history_browser;
 mod keybind;
 mod keys;
+mod layers;
 mod palette;
+mod pixel_selection;
 mod save;
+mod selection;
 mod tool;
 mod tools;
 mod wheel;
 
+use blend::BlendMode;
 use camera::Camera;
 use config::Config;
 use ctx::*;
+use history::{Command, History};
 use keybind::KeyBind;
 use palette::Palette;
+use pixel_selection::{Floating, PixelSelection};
+use selection::Selection;
 use tool::*;
 use wheel::*;
 
 #[derive(clap::Parser)]
 struct Cli {
+    /// Images to import as planes
+    images: Vec<std::path::PathBuf>,
     #[clap(flatten)]
     geng: geng::CliArgs,
 }
 
 pub struct State {
     color: Rgba<f32>,
+    /// Other end of gradients, swapped with the color by a key
+    secondary_color: Rgba<f32>,
     camera: Camera,
-    selected: Option<usize>,
+    selected: Selection,
     model: Model,
+    history: History,
+    palette: Vec<Rgba<f32>>,
+    /// Part of a plane picked with the selection tool
+    pixel_selection: Option<PixelSelection>,
+    floating: Option<Floating>,
+    clipboard: Option<document::Pixels>,
 }
 
 impl State {
     pub fn new(ctx: &Ctx) -> Self {
         let mut model = Model::new(ctx.geng.ugli());
-        model.planes.push(Plane {
-            texture: Texture::new(ctx.geng.ugli()),
-            transform: mat4::identity(),
-        });
+        model
+            .planes
+            .push(Plane::new(Texture::new(ctx.geng.ugli()), mat4::identity()));
         Self {
             camera: Camera {
                 pos: vec3::ZERO,
@@ -53,9 +74,15 @@ impl State {
                 attack: Angle::from_degrees(ctx.config.camera.attack),
                 distance: ctx.config.camera.distance,
             },
-            selected: Some(0),
+            selected: Selection::single(0),
             model,
+            history: History::new(ctx),
             color: ctx.config.default_brush.color,
+            secondary_color: ctx.config.default_brush.secondary_color,
+            palette: ctx.config.default_palette.clone(),
+            pixel_selection: None,
+            floating: None,
+            clipboard: None,
         }
     }
 }
@@ -65,6 +92,11 @@ struct TempTool {
     cancel_on: Option<geng::Event>,
 }
 
+struct Notification {
+    text: String,
+    timer: Timer,
+}
+
 struct Toolbelt {
     primary: AnyTool,
     temp: Option<TempTool>,
@@ -83,20 +115,28 @@ pub struct App {
     ctx: Ctx,
     wheel: Option<Wheel>,
     color_chooser: Option<color::Chooser>,
+    history_browser: Option<history_browser::Browser>,
+    layers: Option<layers::Panel>,
     ui_camera: Camera2d,
     framebuffer_size: vec2<f32>,
     toolbelt: Toolbelt,
     state: State,
     drag_start: Option<vec3<f32>>,
-    load_sender: std::sync::mpsc::Sender<Model>,
-    load_recv: std::sync::mpsc::Receiver<Model>,
-    history: Vec<Model>,
-    history_pos: usize,
+    load_sender: std::sync::mpsc::Sender<anyhow::Result<(Model, Option<document::Scene>)>>,
+    load_recv: std::sync::mpsc::Receiver<anyhow::Result<(Model, Option<document::Scene>)>>,
+    import_sender: std::sync::mpsc::Sender<anyhow::Result<pog_paint::document::Pixels>>,
+    import_recv: std::sync::mpsc::Receiver<anyhow::Result<pog_paint::document::Pixels>>,
+    notification: Option<Notification>,
+    /// Size typed in before exporting a render, like "1920x1080"
+    render_prompt: Option<String>,
+    /// Last size a render was exported with
+    render_size: vec2<usize>,
 }
 
 impl App {
     pub async fn new(ctx: &Ctx) -> Self {
         let (load_sender, load_recv) = std::sync::mpsc::channel();
+        let (import_sender, import_recv) = std::sync::mpsc::channel();
         Self {
             ctx: ctx.clone(),
             framebuffer_size: vec2::splat(1.0),
@@ -114,9 +154,14 @@ impl App {
             drag_start: None,
             load_sender,
             load_recv,
+            import_sender,
+            import_recv,
             color_chooser: None,
-            history: vec![],
-            history_pos: 0,
+            history_browser: None,
+            layers: None,
+            notification: None,
+            render_prompt: None,
+            render_size: vec2(ctx.config.render.width, ctx.config.render.height),
         }
     }
 
@@ -129,22 +174,36 @@ impl App {
             None,
         );
 
-        for plane in &self.state.model.planes {
-            self.ctx.draw_plane(plane, framebuffer, &self.state.camera);
+        self.ctx
+            .draw_planes(&self.state.model, framebuffer, &self.state.camera);
+
+        let model = &self.state.model;
+        for (idx, plane) in model.planes.iter().enumerate() {
+            let in_group = self
+                .state
+                .selected
+                .group()
+                .map_or(false, |group| model.is_in_group(Node::Plane(idx), group));
+            if in_group || self.state.selected.contains(idx) {
+                self.ctx.draw_plane_outline(
+                    plane,
+                    model.world_transform(idx),
+                    framebuffer,
+                    &self.state.camera,
+                );
+            }
         }
 
-        if let Some(idx) = self.state.selected {
-            let plane = &self.state.model.planes[idx];
-            self.ctx
-                .draw_plane_outline(plane, framebuffer, &self.state.camera);
+        if let Some(selection) = &self.state.pixel_selection {
+            selection.draw(&self.ctx, model, framebuffer, &self.state.camera);
+        }
+        if let Some(floating) = &self.state.floating {
+            floating.draw(&self.ctx, model, framebuffer, &self.state.camera);
         }
 
-        if let Some(idx) = self.state.selected {
-            self.ctx.draw_grid(
-                framebuffer,
-                &self.state.camera,
-                self.state.model.planes[idx].transform,
-            );
+        if let Some(idx) = self.state.selected.active() {
+            self.ctx
+                .draw_grid(framebuffer, &self.state.camera, model.world_transform(idx));
         }
 
         let status_pos = self.ui_camera.fov / 2.0 - self.ctx.config.status.width / 2.0;
@@ -176,12 +235,53 @@ impl App {
                 .draw(framebuffer, &self.state.camera, mat4::identity());
         }
 
+        let message = match &self.render_prompt {
+            Some(text) => Some((format!("render size: {text}_"), Rgba::WHITE)),
+            None => self.notification.as_ref().map(|notification| {
+                (
+                    notification.text.clone(),
+                    self.ctx.config.notification.color,
+                )
+            }),
+        };
+        if let Some((text, color)) = message {
+            self.ctx.geng.default_font().draw(
+                framebuffer,
+                &self.ui_camera,
+                &text,
+                vec2::splat(geng::TextAlign::CENTER),
+                mat3::translate(vec2(
+                    0.0,
+                    match self.ctx.config.status.pos {
+                        config::StatusPos::Top => -status_pos,
+                        config::StatusPos::Bottom => status_pos,
+                    },
+                )) * mat3::scale_uniform(self.ctx.config.status.width / 2.0),
+                color,
+            );
+        }
+
         if let Some(wheel) = &self.wheel {
             wheel::draw(self, wheel, framebuffer);
         }
         if let Some(chooser) = &mut self.color_chooser {
             chooser.draw(framebuffer);
         }
+        if let Some(panel) = &mut self.layers {
+            panel.draw(framebuffer, &self.state);
+        }
+        if let Some(browser) = &mut self.history_browser {
+            browser.draw(framebuffer, &self.state);
+        }
+    }
+
+    fn notify(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        log::error!("{text}");
+        self.notification = Some(Notification {
+            text,
+            timer: Timer::new(),
+        });
     }
 
     fn start_wheel(&mut self, typ: WheelType) {
@@ -195,12 +295,84 @@ impl App {
         let mut events = self.ctx.geng.window().events();
         let mut timer = Timer::new();
         while let Some(event) = events.next().await {
-            if let Ok(model) = self.load_recv.try_recv() {
-                self.state.model = model;
+            if let Ok(result) = self.load_recv.try_recv() {
+                match result {
+                    Ok((model, scene)) => {
+                        self.state.model = model;
+                        self.state.floating = None;
+                        self.state.pixel_selection = None;
+                        self.state.history.clear("open file");
+                        if let Some(scene) = scene {
+                            self.state.restore_scene(scene);
+                        }
+                        self.fix_selected();
+                    }
+                    Err(e) => self.notify(format!("Failed to load: {e}")),
+                }
+            }
+            if let Ok(result) = self.import_recv.try_recv() {
+                match result {
+                    Ok(pixels) => self.add_image_plane(pixels),
+                    Err(e) => self.notify(format!("Failed to import image: {e}")),
+                }
+            }
+            if let Some(notification) = &self.notification {
+                if notification.timer.elapsed().as_secs_f64()
+                    > self.ctx.config.notification.duration
+                {
+                    self.notification = None;
+                }
             }
 
             let keys = self.ctx.keys.clone();
 
+            // Typing the size does not trigger other keys
+            if self.handle_render_prompt(&event) {
+                continue;
+            }
+
+            // Panel goes first so that typing a name does not trigger other keys
+            if let Some(panel) = &mut self.layers {
+                if panel.handle_event(&event, &mut self.state) {
+                    self.fix_selected();
+                    continue;
+                }
+            }
+
+            // Goes before the rest so that the shortcuts do not trigger the keys without modifiers
+            if !self.toolbelt.current().is_stroking() {
+                let keys = &keys.pixel_selection;
+                if keys.copy.matches(&event, &self.ctx) {
+                    self.copy_pixels();
+                    continue;
+                }
+                if keys.cut.matches(&event, &self.ctx) {
+                    self.cut_pixels();
+                    continue;
+                }
+                if keys.paste.matches(&event, &self.ctx) {
+                    self.paste_pixels();
+                    continue;
+                }
+                if keys.commit.matches(&event, &self.ctx) {
+                    self.state.commit_floating();
+                }
+                if keys.deselect.matches(&event, &self.ctx) {
+                    self.deselect_pixels();
+                }
+            }
+
+            if keys.layers.toggle.matches(&event, &self.ctx)
+                && !self.toolbelt.current().is_stroking()
+            {
+                if let Some(mut panel) = self.layers.take() {
+                    panel.close(&mut self.state);
+                } else {
+                    self.layers = Some(layers::Panel::new(&self.ctx));
+                }
+                continue;
+            }
+
             if keys.color_chooser.matches(&event, &self.ctx) {
                 if self.color_chooser.is_none() {
                     self.color_chooser =
@@ -219,6 +391,25 @@ impl App {
                 }
             }
 
+            if keys.history_browser.matches(&event, &self.ctx)
+                && !self.toolbelt.current().is_stroking()
+            {
+                if self.history_browser.is_none() {
+                    self.history_browser = Some(history_browser::Browser::new(&self.ctx));
+                } else {
+                    self.history_browser = None;
+                }
+            }
+
+            if let Some(browser) = &mut self.history_browser {
+                if browser.handle_event(&event, &mut self.state) {
+                    self.fix_selected();
+                }
+                if !matches!(event, geng::Event::Draw) {
+                    continue;
+                }
+            }
+
             if keys.undo.matches(&event, &self.ctx) {
                 self.undo();
             }
@@ -227,36 +418,119 @@ impl App {
             }
 
             // TODO color::handle_event(&mut self, &event);
-            if let Some(temp) = &mut self.toolbelt.temp {
+            if let Some(temp) = &self.toolbelt.temp {
                 if temp.cancel_on == Some(event.clone()) {
-                    self.toolbelt.temp = None;
+                    let ray = self.ray(self.ctx.geng.window().cursor_position());
+                    if let Some(mut temp) = self.toolbelt.temp.take() {
+                        temp.tool.end(&mut self.state, ray);
+                    }
                     continue;
                 }
             }
             self.toolbelt.current().handle_event(event.clone());
 
+            // These shortcuts add Ctrl to tool keys, so the event stops here
+            // instead of also switching to the fill or shape tools
             if keys.save.matches(&event, &self.ctx) {
                 self.save();
+                continue;
             }
             if keys.load.matches(&event, &self.ctx) {
                 self.load();
+                continue;
+            }
+            if keys.import_image.matches(&event, &self.ctx) {
+                self.import_image();
+                continue;
+            }
+            if keys.export_gltf.matches(&event, &self.ctx) {
+                self.export_gltf();
+                continue;
+            }
+            if keys.export_render.matches(&event, &self.ctx) {
+                self.start_render_prompt();
+                continue;
+            }
+            if keys.trim.matches(&event, &self.ctx) {
+                self.state.model.trim();
+                continue;
+            }
+
+            if keys.delete_plane.matches(&event, &self.ctx)
+                && !self.toolbelt.current().is_stroking()
+            {
+                self.delete_selected();
+            }
+            if !self.toolbelt.current().is_stroking() {
+                // Plain duplicate also matches with extra modifiers held so it goes last
+                if keys.duplicate_linked.matches(&event, &self.ctx) {
+                    self.duplicate_selected(true);
+                } else if keys.unlink.matches(&event, &self.ctx) {
+                    self.unlink_selected();
+                } else if keys.duplicate.matches(&event, &self.ctx) {
+                    self.duplicate_selected(false);
+                }
+            }
+            if !self.toolbelt.current().is_stroking() {
+                let keys = &keys.group;
+                if keys.create.matches(&event, &self.ctx) {
+                    self.group_selected();
+                }
+                if keys.ungroup.matches(&event, &self.ctx) {
+                    self.ungroup_selected();
+                }
+                if keys.select_parent.matches(&event, &self.ctx) {
+                    self.select_parent();
+                }
             }
 
-            if keys.delete_plane.matches(&event, &self.ctx) {
-                if let Some(idx) = self.state.selected {
-                    self.push_history();
-                    self.state.model.planes.remove(idx);
-                    self.state.selected = None;
+            {
+                let keys = &keys.plane;
+                let opacity_step = if keys.increase_opacity.matches(&event, &self.ctx) {
+                    Some(self.ctx.config.opacity_step)
+                } else if keys.decrease_opacity.matches(&event, &self.ctx) {
+                    Some(-self.ctx.config.opacity_step)
+                } else {
+                    None
+                };
+                let toggle_visible = keys.toggle_visible.matches(&event, &self.ctx);
+                let toggle_locked = keys.toggle_locked.matches(&event, &self.ctx);
+                let commands = self
+                    .state
+                    .selected
+                    .iter()
+                    .filter_map(|idx| {
+                        let old = history::PlaneProperties::of(&self.state.model.planes[idx]);
+                        let mut new = old;
+                        if toggle_visible {
+                            new.visible = !new.visible;
+                        }
+                        if toggle_locked {
+                            new.locked = !new.locked;
+                        }
+                        if let Some(step) = opacity_step {
+                            new.opacity = (new.opacity + step).clamp(0.0, 1.0);
+                        }
+                        (new != old).then_some(Command::Properties {
+                            plane: idx,
+                            old,
+                            new,
+                        })
+                    })
+                    .collect();
+                if let Some(command) = Command::group(commands) {
+                    self.state.history.apply(&mut self.state.model, command);
                 }
             }
 
             if keys.switch_plane.matches(&event, &self.ctx) {
                 if self.state.model.planes.is_empty() {
-                    self.state.selected = None;
+                    self.state.selected = Selection::new();
                 } else {
-                    self.state.selected = Some(
+                    self.state.selected = Selection::single(
                         self.state
                             .selected
+                            .active()
                             .map_or(0, |idx| (idx + 1) % self.state.model.planes.len()),
                     );
                 }
@@ -269,6 +543,14 @@ impl App {
             if keys.palette.matches(&event, &self.ctx) {
                 Palette::start(&mut self);
             }
+            if keys.swap_colors.matches(&event, &self.ctx) {
+                std::mem::swap(&mut self.state.color, &mut self.state.secondary_color);
+            }
+            if keys.add_to_palette.matches(&event, &self.ctx)
+                && !self.state.palette.contains(&self.state.color)
+            {
+                self.state.palette.push(self.state.color);
+            }
 
             // https://stackoverflow.com/questions/31403723/how-to-declare-a-higher-ranked-lifetime-for-a-closure-argument
             fn constrain_tool<
@@ -299,6 +581,37 @@ impl App {
                         return Some((AnyTool::new(tools::Transform::new(&self.ctx)), bind));
                     }
                 }
+                if let Some(bind) = &keys.fill {
+                    if bind.matches(&event, &self.ctx) {
+                        return Some((AnyTool::new(tools::Fill::new(&self.ctx)), bind));
+                    }
+                }
+                for (bind, kind) in [
+                    (&keys.line, tools::ShapeKind::Line),
+                    (&keys.rectangle, tools::ShapeKind::Rectangle),
+                    (&keys.ellipse, tools::ShapeKind::Ellipse),
+                ] {
+                    if let Some(bind) = bind {
+                        if bind.matches(&event, &self.ctx) {
+                            return Some((AnyTool::new(tools::Shape::new(&self.ctx, kind)), bind));
+                        }
+                    }
+                }
+                for (bind, mode) in [
+                    (&keys.select, tools::SelectMode::Rectangle),
+                    (&keys.lasso, tools::SelectMode::Lasso),
+                ] {
+                    if let Some(bind) = bind {
+                        if bind.matches(&event, &self.ctx) {
+                            return Some((AnyTool::new(tools::Select::new(&self.ctx, mode)), bind));
+                        }
+                    }
+                }
+                if let Some(bind) = &keys.gradient {
+                    if bind.matches(&event, &self.ctx) {
+                        return Some((AnyTool::new(tools::Gradient::new(&self.ctx)), bind));
+                    }
+                }
                 if let Some(bind) = &keys.pick {
                     if bind.matches(&event, &self.ctx) {
                         return Some((AnyTool::new(tools::Pick::new(&self.ctx)), bind));
@@ -345,7 +658,6 @@ impl App {
                         }
                     } else {
                         let ray = self.ray(self.ctx.geng.window().cursor_position());
-                        self.push_history();
                         self.toolbelt.current().start(&mut self.state, ray);
                     }
                 }
@@ -482,6 +794,8 @@ impl App {
         if self.toolbelt.current().is_stroking() {
             return;
         }
+        // Painting under floating pixels would go into the history before the lift does
+        self.state.commit_floating();
         self.toolbelt.primary = tool;
     }
 
@@ -522,20 +836,8 @@ impl App {
     fn start_camera_look(&mut self) {
         let ray = self.ray(self.ctx.geng.window().cursor_position());
 
-        let mut closest = None::<f32>;
-        for plane in &self.state.model.planes {
-            if let Some(raycast) = plane.raycast(ray) {
-                if plane.texture.color_at(raycast.texture_pos).a == 0.0 {
-                    continue;
-                }
-                closest = match closest {
-                    Some(current) => Some(current.min(raycast.t)),
-                    None => Some(raycast.t),
-                };
-            }
-        }
-        if let Some(t) = closest {
-            self.drag_start = Some(ray.from + ray.dir * t);
+        if let Some((_idx, raycast)) = self.state.model.raycast(ray, RaycastOptions::default()) {
+            self.drag_start = Some(ray.from + ray.dir * raycast.t);
         }
 
         if self.state.camera.distance != 0.0 {
@@ -550,40 +852,179 @@ impl App {
         self.drag_start = None;
     }
 
-    fn push_history(&mut self) {
-        self.history.truncate(self.history_pos);
-        self.history.push(self.state.model.clone());
-        self.history_pos += 1;
-    }
     fn undo(&mut self) {
-        if self.history_pos >= self.history.len() {
-            self.push_history();
-            self.history_pos -= 1;
+        if self.toolbelt.current().is_stroking() {
+            return;
         }
-        if self.history_pos != 0 {
-            self.history_pos -= 1;
-            self.state.model = self.history[self.history_pos].clone();
+        // Floating pixels are not in the history yet, the first undo puts them back
+        if self.state.cancel_floating(self.ctx.geng.ugli()) {
+            return;
         }
+        self.state.put_down_pixels();
+        self.state.history.undo(&mut self.state.model);
         self.fix_selected();
     }
     fn redo(&mut self) {
-        if self.history_pos + 1 < self.history.len() {
-            self.history_pos += 1;
-            self.state.model = self.history[self.history_pos].clone();
+        if self.toolbelt.current().is_stroking() {
+            return;
         }
+        self.state.put_down_pixels();
+        self.state.history.redo(&mut self.state.model);
         self.fix_selected();
     }
     fn fix_selected(&mut self) {
-        if let Some(idx) = self.state.selected {
-            if self.state.model.planes.get(idx).is_none() {
-                self.state.selected = None;
-            }
+        self.state
+            .selected
+            .retain_valid(self.state.model.planes.len(), self.state.model.groups.len());
+    }
+
+    fn delete_selected(&mut self) {
+        let mut indices: Vec<usize> = self
+            .state
+            .selected
+            .iter()
+            .filter(|&idx| !self.state.model.planes[idx].locked)
+            .collect();
+        // Removing from the end keeps the rest of the indices valid
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        if !indices.is_empty() {
+            self.state.commit_floating();
+        }
+        for &index in &indices {
+            self.state.pixels_plane_removed(index);
+        }
+        let commands = indices
+            .iter()
+            .map(|&index| Command::Delete { index, plane: None })
+            .collect();
+        if let Some(command) = Command::group(commands) {
+            self.state.history.apply(&mut self.state.model, command);
+            self.state.selected.clear();
+        }
+    }
+
+    /// Copies of the selected planes are added on top and become the selection,
+    /// linked copies keep sharing the texture with the original
+    fn duplicate_selected(&mut self, linked: bool) {
+        let mut selected = Selection::new();
+        let mut commands = Vec::new();
+        for idx in self.state.selected.iter() {
+            let plane = &self.state.model.planes[idx];
+            let plane = if linked {
+                plane.clone()
+            } else {
+                plane.unlinked()
+            };
+            commands.push(Command::Create {
+                index: self.state.model.planes.len(),
+                plane: None,
+            });
+            self.state.model.planes.push(plane);
+            selected.add(self.state.model.planes.len() - 1);
+        }
+        if let Some(command) = Command::group(commands) {
+            self.state.history.push(&self.state.model, command);
+            self.state.selected = selected;
+        }
+    }
+
+    /// Gives every selected linked plane its own copy of the texture
+    fn unlink_selected(&mut self) {
+        // Lifted pixels have to be put down on the texture they were lifted from
+        self.state.commit_floating();
+        let model = &self.state.model;
+        let commands = self
+            .state
+            .selected
+            .iter()
+            .filter(|&idx| model.linked_planes(idx).next().is_some())
+            .map(|idx| Command::Unlink {
+                plane: idx,
+                texture: Rc::new(RefCell::new(model.planes[idx].texture.borrow().clone())),
+            })
+            .collect();
+        if let Some(command) = Command::group(commands) {
+            self.state.history.apply(&mut self.state.model, command);
+        }
+    }
+
+    /// Puts the selected group, or the selected planes, into a new group which becomes selected
+    fn group_selected(&mut self) {
+        let model = &mut self.state.model;
+        let nodes: Vec<Node> = match self.state.selected.group() {
+            Some(group) => vec![Node::Group(group)],
+            None => self.state.selected.iter().map(Node::Plane).collect(),
+        };
+        let Some(&active) = nodes.last() else {
+            return;
+        };
+        // New group goes last so that the indices of the other groups stay the same
+        let index = model.groups.len();
+        model.insert_group(
+            index,
+            Group::new(model.node_parent(active), mat4::identity()),
+        );
+        let mut commands = vec![Command::CreateGroup { index, group: None }];
+        for node in nodes {
+            let mut command = Command::set_parent(model, node, Some(index));
+            command.apply(model);
+            commands.push(command);
         }
+        self.state
+            .history
+            .push(&self.state.model, Command::Group(commands));
+        self.state.selected.set_group(Some(index));
+    }
+
+    /// Moves everything out of the selected group, or the group of the active plane,
+    /// and deletes it
+    fn ungroup_selected(&mut self) {
+        let model = &self.state.model;
+        let Some(group) = self.state.selected.group().or_else(|| {
+            self.state
+                .selected
+                .active()
+                .and_then(|idx| model.planes[idx].parent)
+        }) else {
+            return;
+        };
+        let parent = model.groups[group].parent;
+        let children = (0..model.planes.len())
+            .map(Node::Plane)
+            .chain((0..model.groups.len()).map(Node::Group))
+            .filter(|&node| model.node_parent(node) == Some(group));
+        let mut commands: Vec<Command> = children
+            .map(|node| Command::set_parent(model, node, parent))
+            .collect();
+        commands.push(Command::DeleteGroup {
+            index: group,
+            group: None,
+        });
+        self.state
+            .history
+            .apply(&mut self.state.model, Command::Group(commands));
+        self.state.selected.group_removed(group);
+    }
+
+    /// Selects the group above the selected group or the active plane,
+    /// going above the topmost group selects just the planes again
+    fn select_parent(&mut self) {
+        let model = &self.state.model;
+        let parent = match self.state.selected.group() {
+            Some(group) => model.groups[group].parent,
+            None => self
+                .state
+                .selected
+                .active()
+                .and_then(|idx| model.planes[idx].parent),
+        };
+        self.state.selected.set_group(parent);
     }
 }
 
 fn main() {
     let cli: Cli = clap::Parser::parse();
+    let images = cli.images;
     geng::Geng::run_with(
         &{
             let mut options = geng::ContextOptions::default();
@@ -592,7 +1033,11 @@ fn main() {
             options
         },
         |geng| async move {
-            App::new(&Ctx::new(&geng).await).await.run().await;
+            let mut app = App::new(&Ctx::new(&geng).await).await;
+            app.import_paths(images);
+            #[cfg(target_arch = "wasm32")]
+            app.listen_for_dropped_files();
+            app.run().await;
         },
     );
 }
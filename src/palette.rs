@@ -6,14 +6,17 @@ pub struct Palette {
 }
 
 impl Palette {
-    pub fn new(ctx: &Ctx) -> Self {
+    pub fn new(ctx: &Ctx, colors: &[Rgba<f32>]) -> Self {
         Self {
             ctx: ctx.clone(),
-            colors: ctx.config.default_palette.clone(),
+            colors: colors.to_vec(),
         }
     }
     pub fn start(app: &mut App) {
-        app.start_wheel(WheelType::Items(Box::new(Self::new(&app.ctx))))
+        app.start_wheel(WheelType::Items(Box::new(Self::new(
+            &app.ctx,
+            &app.state.palette,
+        ))))
     }
 }
 
@@ -0,0 +1,528 @@
+use super::*;
+
+/// Which pixels of an area in plane pixel space are included
+#[derive(Clone)]
+pub struct Mask {
+    pub area: Aabb2<i32>,
+    data: Vec<bool>,
+}
+
+impl Mask {
+    pub fn rect(area: Aabb2<i32>) -> Self {
+        let size = area.size().map(|x| x.max(0) as usize);
+        Self {
+            area,
+            data: vec![true; size.x * size.y],
+        }
+    }
+
+    /// Pixels with their centers inside the polygon, parts that overlap an odd number
+    /// of times are inside
+    pub fn polygon(points: &[vec2<f32>]) -> Self {
+        let area = Aabb2::points_bounding_box(points.iter().copied()).map_or(
+            Aabb2::point(vec2::ZERO),
+            |bb| Aabb2 {
+                min: bb.min.map(|x| x.floor() as i32),
+                max: bb.max.map(|x| x.ceil() as i32),
+            },
+        );
+        let mut result = Self::rect(area);
+        result.data.fill(false);
+        for y in area.min.y..area.max.y {
+            let center = y as f32 + 0.5;
+            // Where the edges cross the row of pixel centers
+            let mut crossings: Vec<f32> = points
+                .iter()
+                .zip(points.iter().cycle().skip(1))
+                .filter(|(a, b)| (a.y <= center) != (b.y <= center))
+                .map(|(a, b)| a.x + (center - a.y) / (b.y - a.y) * (b.x - a.x))
+                .collect();
+            crossings.sort_by_key(|&x| r32(x));
+            for span in crossings.chunks_exact(2) {
+                let [from, to] = [span[0], span[1]].map(|x| (x - 0.5).ceil() as i32);
+                for x in from.max(area.min.x)..to.min(area.max.x) {
+                    let index = result.index(vec2(x, y)).unwrap();
+                    result.data[index] = true;
+                }
+            }
+        }
+        result
+    }
+
+    fn index(&self, pos: vec2<i32>) -> Option<usize> {
+        let area = self.area;
+        if pos.x < area.min.x || pos.y < area.min.y || pos.x >= area.max.x || pos.y >= area.max.y {
+            return None;
+        }
+        let pos = (pos - area.min).map(|x| x as usize);
+        Some(pos.y * area.width() as usize + pos.x)
+    }
+
+    pub fn contains(&self, pos: vec2<i32>) -> bool {
+        self.index(pos).map_or(false, |index| self.data[index])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.data.contains(&true)
+    }
+
+    /// Makes the pixels outside of the mask transparent, the pixels cover the area
+    pub fn cut_out(&self, pixels: &mut document::Pixels) {
+        self.for_each_excluded(pixels, |pixels, pos| {
+            pixels.set(pos, Rgba::TRANSPARENT_BLACK)
+        });
+    }
+
+    /// Makes the pixels inside of the mask transparent, the pixels cover the area
+    pub fn erase(&self, pixels: &mut document::Pixels) {
+        let inverted = Self {
+            area: self.area,
+            data: self.data.iter().map(|&included| !included).collect(),
+        };
+        inverted.cut_out(pixels);
+    }
+
+    fn for_each_excluded(
+        &self,
+        pixels: &mut document::Pixels,
+        mut f: impl FnMut(&mut document::Pixels, vec2<usize>),
+    ) {
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                if !self.data[y * pixels.size.x + x] {
+                    f(pixels, vec2(x, y));
+                }
+            }
+        }
+    }
+
+    /// Included pixels are white, so that the outline can be drawn around them
+    fn to_texture(&self, ugli: &Ugli) -> Texture {
+        let mut pixels = document::Pixels::new(self.area.size().map(|x| x.max(0) as usize));
+        pixels.data.fill(255);
+        self.cut_out(&mut pixels);
+        Texture::from_pixels(ugli, &pixels, self.area.min)
+    }
+}
+
+/// Pixels of a plane picked with the selection tool
+pub struct PixelSelection {
+    /// Kept up to date like [Selection] when planes are moved or deleted
+    pub plane: usize,
+    pub mask: Mask,
+    /// Only the outline of it is drawn
+    preview: Plane,
+}
+
+impl PixelSelection {
+    pub fn new(ugli: &Ugli, plane: usize, mask: Mask) -> Self {
+        Self {
+            plane,
+            preview: Plane::new(mask.to_texture(ugli), mat4::identity()),
+            mask,
+        }
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &Ctx,
+        model: &Model,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera,
+    ) {
+        ctx.draw_preview(
+            &self.preview,
+            model.world_transform(self.plane),
+            framebuffer,
+            camera,
+            false,
+        );
+    }
+}
+
+/// Where floating pixels were lifted from, so that they can be put back
+struct Lifted {
+    /// Covers the hole, the commit adds the pixels so that both are a single command
+    edit: TextureEdit,
+    mask: Mask,
+    /// Pixels of the mask area before they were lifted
+    original: document::Pixels,
+}
+
+/// Pixels lifted off a plane or pasted onto it,
+/// they can be moved around on top of it until committed
+pub struct Floating {
+    /// Kept up to date like [Selection] when planes are moved or deleted
+    pub plane: usize,
+    /// Bottom left corner in plane pixel space
+    pub pos: vec2<i32>,
+    pixels: document::Pixels,
+    /// Texture is at the origin, drawn moved to the position
+    preview: Plane,
+    /// None for pasted pixels
+    lifted: Option<Lifted>,
+}
+
+impl Floating {
+    pub fn new(ugli: &Ugli, plane: usize, pos: vec2<i32>, pixels: document::Pixels) -> Self {
+        Self {
+            plane,
+            pos,
+            preview: Plane::new(
+                Texture::from_pixels(ugli, &pixels, vec2::ZERO),
+                mat4::identity(),
+            ),
+            pixels,
+            lifted: None,
+        }
+    }
+
+    pub fn pixels(&self) -> &document::Pixels {
+        &self.pixels
+    }
+
+    pub fn area(&self) -> Aabb2<i32> {
+        Aabb2::point(self.pos).extend_positive(self.pixels.size.map(|x| x as i32))
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &Ctx,
+        model: &Model,
+        framebuffer: &mut ugli::Framebuffer,
+        camera: &Camera,
+    ) {
+        let transform = model.world_transform(self.plane)
+            * mat4::translate(self.pos.map(|x| x as f32).extend(0.0));
+        ctx.draw_preview(&self.preview, transform, framebuffer, camera, true);
+    }
+}
+
+impl State {
+    /// Floating pixels, or the ones in the pixel selection with everything around them transparent
+    pub fn selected_pixels(&self) -> Option<document::Pixels> {
+        match &self.floating {
+            Some(floating) => Some(floating.pixels.clone()),
+            None => self.masked_pixels(),
+        }
+    }
+
+    fn masked_pixels(&self) -> Option<document::Pixels> {
+        let selection = self.pixel_selection.as_ref()?;
+        let mut pixels = self.model.planes[selection.plane]
+            .texture
+            .borrow()
+            .read_pixels(selection.mask.area);
+        selection.mask.cut_out(&mut pixels);
+        Some(pixels)
+    }
+
+    /// Clears the selected pixels on the plane, the selection itself stays
+    pub fn erase_selection(&mut self) {
+        let Some(selection) = &self.pixel_selection else {
+            return;
+        };
+        let idx = selection.plane;
+        if !self.model.planes[idx].editable() {
+            return;
+        }
+        let area = selection.mask.area;
+        let mut texture = self.model.planes[idx].texture.borrow_mut();
+        let mut pixels = texture.read_pixels(area);
+        selection.mask.erase(&mut pixels);
+        let mut edit = TextureEdit::new();
+        edit.touch(&texture, area);
+        texture.write_pixels(area.min, &pixels);
+        drop(texture);
+        if let Some(command) = Command::paint(&self.model, idx, edit) {
+            self.history.push(&self.model, command);
+        }
+    }
+
+    /// Turns the pixel selection into floating pixels, leaving a hole in the plane
+    ///
+    /// The hole only goes into the history once the pixels are committed
+    pub fn lift_selection(&mut self, ugli: &Ugli) {
+        self.commit_floating();
+        let Some(pixels) = self.masked_pixels() else {
+            return;
+        };
+        let idx = self.pixel_selection.as_ref().unwrap().plane;
+        if !self.model.planes[idx].editable() {
+            return;
+        }
+        let selection = self.pixel_selection.take().unwrap();
+        let area = selection.mask.area;
+        let mut texture = self.model.planes[idx].texture.borrow_mut();
+        let original = texture.read_pixels(area);
+        let mut hole = original.clone();
+        selection.mask.erase(&mut hole);
+        let mut edit = TextureEdit::new();
+        edit.touch(&texture, area);
+        texture.write_pixels(area.min, &hole);
+        drop(texture);
+        let mut floating = Floating::new(ugli, idx, area.min, pixels);
+        floating.lifted = Some(Lifted {
+            edit,
+            mask: selection.mask,
+            original,
+        });
+        self.floating = Some(floating);
+    }
+
+    /// Draws the floating pixels over the plane they are on
+    ///
+    /// They are kept floating if the plane can not be painted on
+    pub fn commit_floating(&mut self) {
+        let Some(floating) = &self.floating else {
+            return;
+        };
+        let idx = floating.plane;
+        if !self.model.planes[idx].editable() {
+            return;
+        }
+        let floating = self.floating.take().unwrap();
+        let area = floating.area();
+        let mut edit = floating
+            .lifted
+            .map_or_else(TextureEdit::new, |lifted| lifted.edit);
+        let mut texture = self.model.planes[idx].texture.borrow_mut();
+        let mut pixels = texture.read_pixels(area);
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                let pos = vec2(x, y);
+                let color = blend::over(
+                    pixels.get(pos).convert(),
+                    floating.pixels.get(pos).convert(),
+                );
+                pixels.set(pos, color.convert());
+            }
+        }
+        edit.touch(&texture, area);
+        texture.write_pixels(area.min, &pixels);
+        drop(texture);
+        if let Some(command) = Command::paint(&self.model, idx, edit) {
+            self.history.push(&self.model, command);
+        }
+    }
+
+    /// Throws the floating pixels away, the hole they were lifted from stays
+    pub fn drop_floating(&mut self) {
+        let Some(floating) = self.floating.take() else {
+            return;
+        };
+        if let Some(lifted) = floating.lifted {
+            if let Some(command) = Command::paint(&self.model, floating.plane, lifted.edit) {
+                self.history.push(&self.model, command);
+            }
+        }
+    }
+
+    /// Lifted pixels go back where they were, pasted ones are dropped,
+    /// returns the plane and the mask of the lifted pixels
+    fn put_back_floating(&mut self) -> Option<(usize, Mask)> {
+        let floating = self.floating.take()?;
+        let lifted = floating.lifted?;
+        self.model.planes[floating.plane]
+            .texture
+            .borrow_mut()
+            .write_pixels(lifted.mask.area.min, &lifted.original);
+        Some((floating.plane, lifted.mask))
+    }
+
+    /// Undoes lifting or pasting without going through the history, since it has
+    /// nothing of the floating pixels yet. Lifted pixels are selected again
+    ///
+    /// Returns false if nothing was floating
+    pub fn cancel_floating(&mut self, ugli: &Ugli) -> bool {
+        if self.floating.is_none() {
+            return false;
+        }
+        if let Some((plane, mask)) = self.put_back_floating() {
+            self.pixel_selection = Some(PixelSelection::new(ugli, plane, mask));
+        }
+        true
+    }
+
+    /// Commits the floating pixels and drops the selection before moving through history
+    ///
+    /// Floating pixels that can not be committed are put back where they were
+    pub fn put_down_pixels(&mut self) {
+        self.commit_floating();
+        self.put_back_floating();
+        self.pixel_selection = None;
+    }
+
+    /// Updates the plane of the selected pixels before a plane is removed from the model,
+    /// they are dropped along with their plane
+    pub fn pixels_plane_removed(&mut self, index: usize) {
+        if self
+            .floating
+            .as_ref()
+            .map_or(false, |floating| floating.plane == index)
+        {
+            self.put_back_floating();
+        }
+        if let Some(selection) = &mut self.pixel_selection {
+            match selection::index_after_removal(selection.plane, index) {
+                Some(plane) => selection.plane = plane,
+                None => self.pixel_selection = None,
+            }
+        }
+        if let Some(floating) = &mut self.floating {
+            match selection::index_after_removal(floating.plane, index) {
+                Some(plane) => floating.plane = plane,
+                None => self.floating = None,
+            }
+        }
+    }
+
+    /// Updates the plane of the selected pixels after a plane was moved from one index to another
+    pub fn pixels_plane_moved(&mut self, from: usize, to: usize) {
+        if let Some(selection) = &mut self.pixel_selection {
+            selection.plane = selection::index_after_move(selection.plane, from, to);
+        }
+        if let Some(floating) = &mut self.floating {
+            floating.plane = selection::index_after_move(floating.plane, from, to);
+        }
+    }
+}
+
+impl App {
+    pub fn copy_pixels(&mut self) {
+        if let Some(pixels) = self.state.selected_pixels() {
+            self.state.clipboard = Some(pixels);
+        }
+    }
+
+    /// Pixels are only cut from planes that can be painted on, otherwise nothing happens
+    pub fn cut_pixels(&mut self) {
+        let plane = match (&self.state.floating, &self.state.pixel_selection) {
+            (Some(floating), _) => floating.plane,
+            (None, Some(selection)) => selection.plane,
+            (None, None) => return,
+        };
+        if !self.state.model.planes[plane].editable() {
+            self.notify("Can not cut from a hidden or locked plane");
+            return;
+        }
+        self.copy_pixels();
+        if self.state.floating.is_some() {
+            self.state.drop_floating();
+        } else {
+            self.state.erase_selection();
+        }
+        self.state.pixel_selection = None;
+    }
+
+    /// Clipboard becomes floating pixels centered at the cursor, on the plane under it
+    /// or on the active plane when there is nothing drawn under the cursor
+    pub fn paste_pixels(&mut self) {
+        let Some(pixels) = self.state.clipboard.clone() else {
+            return;
+        };
+        let ray = self.ray(self.ctx.geng.window().cursor_position());
+        let model = &self.state.model;
+        let Some((idx, raycast)) = model
+            .raycast(ray, RaycastOptions::default())
+            .filter(|(idx, _raycast)| model.planes[*idx].editable())
+            .or_else(|| {
+                let idx = self.state.selected.active()?;
+                let raycast = model.planes[idx].raycast(model.world_transform(idx), ray)?;
+                Some((idx, raycast))
+            })
+        else {
+            return;
+        };
+        let size = pixels.size.map(|x| x as f32);
+        let pos = (raycast.texture_pos - size / 2.0).map(|x| x.round() as i32);
+        self.state.commit_floating();
+        self.state.pixel_selection = None;
+        self.state.floating = Some(Floating::new(self.ctx.geng.ugli(), idx, pos, pixels));
+        self.state.selected = Selection::single(idx);
+    }
+
+    /// Floating pixels are committed so that nothing is lost
+    pub fn deselect_pixels(&mut self) {
+        self.state.commit_floating();
+        self.state.pixel_selection = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(points: &[(f32, f32)]) -> Vec<vec2<f32>> {
+        points.iter().map(|&(x, y)| vec2(x, y)).collect()
+    }
+
+    fn count(mask: &Mask) -> usize {
+        mask.data.iter().filter(|&&included| included).count()
+    }
+
+    #[test]
+    fn polygon_includes_pixel_centers_inside() {
+        let mask = Mask::polygon(&points(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)]));
+        assert_eq!(
+            mask.area,
+            Aabb2::point(vec2(0, 0)).extend_positive(vec2(4, 4))
+        );
+        assert_eq!(count(&mask), 6);
+        assert!(mask.contains(vec2(0, 0)));
+        assert!(mask.contains(vec2(2, 0)));
+        assert!(mask.contains(vec2(1, 1)));
+        // Center is right on the edge
+        assert!(!mask.contains(vec2(3, 0)));
+        assert!(!mask.contains(vec2(2, 2)));
+        assert!(!mask.contains(vec2(-1, 0)));
+    }
+
+    #[test]
+    fn polygon_overlapping_itself_has_holes() {
+        // Square with a smaller one inside, joined by an edge that goes both ways
+        let mask = Mask::polygon(&points(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (1.0, 3.0),
+            (3.0, 3.0),
+            (3.0, 1.0),
+            (1.0, 1.0),
+        ]));
+        assert_eq!(count(&mask), 12);
+        for pos in [vec2(1, 1), vec2(2, 1), vec2(1, 2), vec2(2, 2)] {
+            assert!(!mask.contains(pos));
+        }
+        assert!(mask.contains(vec2(0, 1)));
+        assert!(mask.contains(vec2(3, 2)));
+    }
+
+    #[test]
+    fn polygon_without_area_is_empty() {
+        assert!(Mask::polygon(&points(&[(0.0, 0.0), (5.0, 5.0)])).is_empty());
+        assert!(Mask::polygon(&[]).is_empty());
+    }
+
+    #[test]
+    fn cut_out_and_erase_are_opposite() {
+        let mask = Mask::polygon(&points(&[(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)]));
+        let mut full = document::Pixels::new(vec2(2, 2));
+        full.data.fill(255);
+        let mut cut = full.clone();
+        mask.cut_out(&mut cut);
+        let mut erased = full.clone();
+        mask.erase(&mut erased);
+        for y in 0..2 {
+            for x in 0..2 {
+                let pos = vec2(x, y);
+                let inside = mask.contains(pos.map(|x| x as i32));
+                assert_eq!(cut.get(pos).a == 255, inside);
+                assert_eq!(erased.get(pos).a == 255, !inside);
+            }
+        }
+    }
+}
@@ -1,11 +1,142 @@
 use super::*;
 
+use pog_paint::document;
+
+use std::path::PathBuf;
+
+/// Width and height separated by an x
+fn parse_size(text: &str) -> Option<vec2<usize>> {
+    let (width, height) = text.trim().split_once(['x', 'X'])?;
+    let size = vec2(width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (size.x > 0 && size.y > 0).then_some(size)
+}
+
+/// Any format the image crate can guess from the data
+fn decode_image(data: &[u8]) -> anyhow::Result<document::Pixels> {
+    let image = image::load_from_memory(data)?.to_rgba8();
+    Ok(document::Pixels::from_image(&image))
+}
+
+impl State {
+    pub fn scene(&self) -> document::Scene {
+        document::Scene {
+            camera: document::Camera {
+                pos: self.camera.pos,
+                rot: self.camera.rot.as_radians(),
+                attack: self.camera.attack.as_radians(),
+                distance: self.camera.distance,
+                fov: self.camera.fov.as_radians(),
+            },
+            selected: self.selected.active(),
+            color: self.color,
+            palette: self.palette.clone(),
+        }
+    }
+
+    pub fn restore_scene(&mut self, scene: document::Scene) {
+        self.camera = Camera {
+            pos: scene.camera.pos,
+            rot: Angle::from_radians(scene.camera.rot),
+            attack: Angle::from_radians(scene.camera.attack),
+            distance: scene.camera.distance,
+            fov: Angle::from_radians(scene.camera.fov),
+        };
+        self.selected = scene
+            .selected
+            .map_or_else(Selection::new, Selection::single);
+        self.color = scene.color;
+        self.palette = scene.palette;
+    }
+}
+
 impl App {
-    pub fn save(&self) {
+    pub fn save(&mut self) {
+        self.state.commit_floating();
+        self.state.model.trim();
+        let mut document = self.state.model.to_document();
+        document.scene = Some(self.state.scene());
         let mut data = Vec::new();
-        self.state.model.save(&mut data).unwrap();
+        document.save(&mut data).unwrap();
         let _ = file_dialog::save("model.pp", &data);
     }
+    pub fn export_gltf(&self) {
+        let data = pog_paint::export::gltf::to_glb(&self.state.model.to_document());
+        let _ = file_dialog::save("model.glb", &data);
+    }
+    /// Asks for the size of the render, starting with the last one used
+    pub fn start_render_prompt(&mut self) {
+        let text = format!("{}x{}", self.render_size.x, self.render_size.y);
+        self.ctx.geng.window().start_text_edit(&text);
+        self.render_prompt = Some(text);
+    }
+
+    /// Returns true if the event was used by the prompt,
+    /// enter exports the render and escape cancels it
+    pub fn handle_render_prompt(&mut self, event: &geng::Event) -> bool {
+        let Some(text) = &mut self.render_prompt else {
+            return false;
+        };
+        match event {
+            geng::Event::EditText(new_text) => *text = new_text.clone(),
+            geng::Event::KeyPress {
+                key: geng::Key::Enter,
+            } => {
+                let text = self.render_prompt.take().unwrap();
+                self.ctx.geng.window().stop_text_edit();
+                match parse_size(&text) {
+                    Some(size) => {
+                        self.render_size = size;
+                        self.export_render(size);
+                    }
+                    None => self.notify(format!(
+                        "Render size should be like 1920x1080, not {text:?}"
+                    )),
+                }
+            }
+            geng::Event::KeyPress {
+                key: geng::Key::Escape,
+            } => {
+                self.render_prompt = None;
+                self.ctx.geng.window().stop_text_edit();
+            }
+            _ => {}
+        }
+        !matches!(event, geng::Event::Draw)
+    }
+
+    pub fn export_render(&self, size: vec2<usize>) {
+        let config = &self.ctx.config.render;
+        let ugli = self.ctx.geng.ugli();
+        let mut texture = ugli::Texture::new_uninitialized(ugli, size);
+        let mut depth = ugli::Renderbuffer::<ugli::DepthComponent>::new(ugli, size);
+        {
+            let mut framebuffer = ugli::Framebuffer::new(
+                ugli,
+                ugli::ColorAttachment::Texture(&mut texture),
+                ugli::DepthAttachment::Renderbuffer(&mut depth),
+            );
+            let framebuffer = &mut framebuffer;
+            ugli::clear(
+                framebuffer,
+                Some(if config.transparent_background {
+                    Rgba::TRANSPARENT_BLACK
+                } else {
+                    self.ctx.config.background_color
+                }),
+                Some(1.0),
+                None,
+            );
+            self.ctx
+                .draw_planes(&self.state.model, framebuffer, &self.state.camera);
+        }
+        let framebuffer =
+            ugli::FramebufferRead::new_color(ugli, ugli::ColorAttachmentRead::Texture(&texture));
+        let pixels = pog_paint::document::Pixels {
+            size,
+            data: framebuffer.read_color().data().to_vec(),
+        };
+        let _ = file_dialog::save("render.png", &pixels.encode_png());
+    }
     pub fn load(&mut self) {
         let sender = self.load_sender.clone();
         let ctx = self.ctx.clone();
@@ -15,13 +146,135 @@ impl App {
                 .window()
                 .spawn(async move {
                     let ctx = &ctx;
-                    let _ = sender.send(
-                        pog_paint::Model::load(ctx.geng.asset_manager(), file.reader().unwrap())
-                            .await
-                            .unwrap(),
-                    );
+                    let result = async {
+                        let mut data = Vec::new();
+                        std::pin::pin!(file.reader()?)
+                            .read_to_end(&mut data)
+                            .await?;
+                        let mut document = Document::load(data.as_slice())?;
+                        let scene = document.scene.take();
+                        let model =
+                            Model::from_document(ctx.geng.asset_manager(), document).await?;
+                        anyhow::Ok((model, scene))
+                    }
+                    .await;
+                    let _ = sender.send(result);
                 })
                 .detach();
         });
     }
+    pub fn import_image(&mut self) {
+        let sender = self.import_sender.clone();
+        let ctx = self.ctx.clone();
+        file_dialog::select(|file| {
+            ctx.clone()
+                .geng
+                .window()
+                .spawn(async move {
+                    let result = async {
+                        let mut data = Vec::new();
+                        std::pin::pin!(file.reader()?)
+                            .read_to_end(&mut data)
+                            .await?;
+                        decode_image(&data)
+                    }
+                    .await;
+                    let _ = sender.send(result);
+                })
+                .detach();
+        });
+    }
+    /// Same as [App::import_image] for files given by path on the command line
+    pub fn import_paths(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            let sender = self.import_sender.clone();
+            self.ctx
+                .geng
+                .window()
+                .spawn(async move {
+                    let result = async {
+                        let mut data = Vec::new();
+                        std::pin::pin!(file::load(&path).await?)
+                            .read_to_end(&mut data)
+                            .await?;
+                        decode_image(&data)
+                    }
+                    .await
+                    .map_err(|e| e.context(format!("{path:?}")));
+                    let _ = sender.send(result);
+                })
+                .detach();
+        }
+    }
+    /// Images dropped onto the page are imported like [App::import_image]
+    #[cfg(target_arch = "wasm32")]
+    pub fn listen_for_dropped_files(&self) {
+        use wasm_bindgen::{closure::Closure, JsCast as _};
+        let document = web_sys::window().unwrap().document().unwrap();
+        // Browsers open the file in place of the page unless dragging over it is cancelled
+        let dragover =
+            Closure::<dyn FnMut(web_sys::DragEvent)>::new(|event: web_sys::DragEvent| {
+                event.prevent_default();
+            });
+        let sender = self.import_sender.clone();
+        let drop =
+            Closure::<dyn FnMut(web_sys::DragEvent)>::new(move |event: web_sys::DragEvent| {
+                event.prevent_default();
+                let Some(files) = event.data_transfer().and_then(|data| data.files()) else {
+                    return;
+                };
+                for file in (0..files.length()).filter_map(|index| files.get(index)) {
+                    let sender = sender.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let result = async {
+                            let buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer())
+                                .await
+                                .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+                            decode_image(&js_sys::Uint8Array::new(&buffer).to_vec())
+                        }
+                        .await
+                        .map_err(|e| e.context(file.name()));
+                        let _ = sender.send(result);
+                    });
+                }
+            });
+        for (name, listener) in [("dragover", dragover), ("drop", drop)] {
+            document
+                .add_event_listener_with_callback(name, listener.as_ref().unchecked_ref())
+                .unwrap();
+            // Listens for as long as the page is open
+            listener.forget();
+        }
+    }
+    pub fn add_image_plane(&mut self, pixels: pog_paint::document::Pixels) {
+        let ray = self.ray(self.ctx.geng.window().cursor_position());
+        let transform = tools::create::new_plane_transform(&self.ctx, &self.state, ray)
+            .unwrap_or_else(|| {
+                tools::create::plane_transform_at(&self.ctx, &self.state, self.state.camera.pos)
+            });
+        let offset = -pixels.size.map(|x| x as i32) / 2;
+        self.state.model.planes.push(Plane::new(
+            Texture::from_pixels(self.ctx.geng.ugli(), &pixels, offset),
+            transform,
+        ));
+        let index = self.state.model.planes.len() - 1;
+        self.state
+            .history
+            .push(&self.state.model, Command::Create { index, plane: None });
+        self.state.selected = Selection::single(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sizes() {
+        assert_eq!(parse_size("1920x1080"), Some(vec2(1920, 1080)));
+        assert_eq!(parse_size(" 64 X 32 "), Some(vec2(64, 32)));
+        assert_eq!(parse_size("0x10"), None);
+        assert_eq!(parse_size("1920"), None);
+        assert_eq!(parse_size("ax10"), None);
+    }
 }
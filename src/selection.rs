@@ -0,0 +1,146 @@
+/// Set of selected plane indices in the order they were selected
+///
+/// The last one is the active plane, the one that is painted on and shows the grid
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    planes: Vec<usize>,
+    /// Group that is transformed as a whole instead of the planes
+    group: Option<usize>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn single(index: usize) -> Self {
+        Self {
+            planes: vec![index],
+            group: None,
+        }
+    }
+
+    pub fn group(&self) -> Option<usize> {
+        self.group
+    }
+
+    /// Selecting a group keeps the planes, so the active one can still be painted on
+    pub fn set_group(&mut self, group: Option<usize>) {
+        self.group = group;
+    }
+
+    pub fn active(&self) -> Option<usize> {
+        self.planes.last().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.planes.is_empty()
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.planes.contains(&index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.planes.iter().copied()
+    }
+
+    /// Makes the plane active, keeping the rest selected
+    pub fn add(&mut self, index: usize) {
+        self.planes.retain(|&other| other != index);
+        self.planes.push(index);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.planes.retain(|&other| other != index);
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if self.contains(index) {
+            self.remove(index);
+        } else {
+            self.add(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.planes.clear();
+        self.group = None;
+    }
+
+    /// Updates the indices after a plane was removed from the model
+    pub fn removed(&mut self, index: usize) {
+        self.planes = self
+            .planes
+            .iter()
+            .filter_map(|&other| index_after_removal(other, index))
+            .collect();
+    }
+
+    /// Updates the indices after a plane was moved from one index to another
+    pub fn moved(&mut self, from: usize, to: usize) {
+        for index in &mut self.planes {
+            *index = index_after_move(*index, from, to);
+        }
+    }
+
+    /// Updates the group index after a group was removed from the model
+    pub fn group_removed(&mut self, index: usize) {
+        self.group = match self.group {
+            Some(group) if group == index => None,
+            Some(group) if group > index => Some(group - 1),
+            group => group,
+        };
+    }
+
+    /// Forgets indices that are out of range
+    pub fn retain_valid(&mut self, plane_count: usize, group_count: usize) {
+        self.planes.retain(|&index| index < plane_count);
+        self.group = self.group.filter(|&index| index < group_count);
+    }
+}
+
+/// Where a plane index points after the plane at the removed index is gone,
+/// none if it was that plane
+pub fn index_after_removal(index: usize, removed: usize) -> Option<usize> {
+    match index.cmp(&removed) {
+        std::cmp::Ordering::Less => Some(index),
+        std::cmp::Ordering::Equal => None,
+        std::cmp::Ordering::Greater => Some(index - 1),
+    }
+}
+
+/// Where a plane index points after a plane was moved from one index to another
+pub fn index_after_move(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_follow_removed_and_moved_planes() {
+        assert_eq!(index_after_removal(1, 2), Some(1));
+        assert_eq!(index_after_removal(2, 2), None);
+        assert_eq!(index_after_removal(3, 2), Some(2));
+
+        // Plane 1 goes on top of planes 2 and 3
+        let moved: Vec<usize> = (0..5).map(|index| index_after_move(index, 1, 3)).collect();
+        assert_eq!(moved, [0, 3, 1, 2, 4]);
+        // And back
+        let moved: Vec<usize> = moved
+            .into_iter()
+            .map(|index| index_after_move(index, 3, 1))
+            .collect();
+        assert_eq!(moved, [0, 1, 2, 3, 4]);
+    }
+}
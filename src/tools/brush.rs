@@ -3,9 +3,18 @@ use super::*;
 pub struct Brush {
     ctx: Ctx,
     size: usize,
+    /// For the eraser it is how much of the alpha is removed
+    opacity: f32,
+    blend_mode: BlendMode,
     eraser: bool,
+    /// Brush tip drawn around [Brush::round_pos] of zero, so it is only rebuilt
+    /// when the size or color changes
+    preview: Option<(PreviewKey, Plane)>,
 }
 
+/// Size and color of the brush tip
+type PreviewKey = (usize, Rgba<f32>);
+
 impl Brush {
     pub fn default(ctx: &Ctx) -> Self {
         Self::new(ctx)
@@ -23,11 +32,14 @@ impl Brush {
         Self {
             ctx: ctx.clone(),
             size: ctx.config.default_brush.size,
+            opacity: ctx.config.default_brush.opacity,
+            blend_mode: ctx.config.default_brush.blend_mode,
             eraser,
+            preview: None,
         }
     }
 
-    fn round_pos(&self, pos: vec2<f32>) -> vec2<f32> {
+    pub(super) fn round_pos(&self, pos: vec2<f32>) -> vec2<f32> {
         if self.size % 2 == 0 {
             pos.map(|x| x.round())
         } else {
@@ -35,73 +47,129 @@ impl Brush {
         }
     }
 
-    fn draw_width(&self) -> f32 {
+    pub(super) fn draw_width(&self) -> f32 {
         let rounded = (self.size as f32 / 2.0).floor() * 2.0;
         (rounded + self.size as f32) / 2.0
     }
 
-    fn draw_line(&self, texture: &mut Texture, p1: vec2<f32>, p2: vec2<f32>, color: Rgba<f32>) {
-        let width = self.draw_width();
-        let bb = {
-            let bb = Aabb2::from_corners(p1, p2).extend_uniform(width);
-            Aabb2 {
-                min: bb.min.map(|x| x.floor() as i32),
-                max: bb.max.map(|x| x.ceil() as i32),
+    /// Pixels affected by drawing a line
+    pub(super) fn line_bounds(&self, p1: vec2<f32>, p2: vec2<f32>) -> Aabb2<i32> {
+        let bb = Aabb2::from_corners(p1, p2).extend_uniform(self.draw_width());
+        Aabb2 {
+            min: bb.min.map(|x| x.floor() as i32),
+            max: bb.max.map(|x| x.ceil() as i32),
+        }
+    }
+
+    /// Covers the pixels with their centers within half the width of the line
+    pub(super) fn cover_line(&self, coverage: &mut Coverage, p1: vec2<f32>, p2: vec2<f32>) {
+        let radius = self.draw_width() / 2.0;
+        let delta = p2 - p1;
+        let len_sqr = vec2::dot(delta, delta);
+        coverage.fill(self.line_bounds(p1, p2), |pos| {
+            let t = if len_sqr == 0.0 {
+                0.0
+            } else {
+                (vec2::dot(pos - p1, delta) / len_sqr).clamp(0.0, 1.0)
+            };
+            (pos - (p1 + delta * t)).len() <= radius
+        });
+    }
+
+    /// Redraws the area from the pixels it had before the edit,
+    /// with the pixels covered by the stroke painted over them
+    ///
+    /// Coverage is kept apart from the plane so that overlapping parts of a stroke do not add up
+    pub(super) fn composite(
+        &self,
+        texture: &mut Texture,
+        edit: &TextureEdit,
+        coverage: &Coverage,
+        area: Aabb2<i32>,
+        color: Rgba<f32>,
+    ) {
+        let mut pixels = edit.original(texture, area);
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                let pos = vec2(x, y);
+                if !coverage.contains(area.min + pos.map(|x| x as i32)) {
+                    continue;
+                }
+                let amount = self.opacity;
+                let dst: Rgba<f32> = pixels.get(pos).convert();
+                let result = if self.eraser {
+                    blend::erase(dst, amount)
+                } else {
+                    let src = Rgba {
+                        a: color.a * amount,
+                        ..color
+                    };
+                    self.blend_mode.blend(dst, src)
+                };
+                pixels.set(pos, result.convert());
             }
-        };
-        texture.draw(bb, |framebuffer, viewport| {
-            let dir = (p2 - p1).normalize_or_zero();
-            let normal = dir.rotate_90();
-            let transform = mat3::translate((p1 + p2) / 2.0)
-                * mat3::from_orts((p2 - p1) / 2.0, normal * width / 2.0);
-            ugli::draw(
-                framebuffer,
-                &self.ctx.shaders.color_2d,
-                ugli::DrawMode::TriangleFan,
-                &*self.ctx.quad,
-                ugli::uniforms! {
-                    u_projection_matrix: mat3::ortho(bb.map(|x| x as f32)),
-                    u_view_matrix: mat3::identity(),
-                    u_transform: transform,
-                    u_color: color,
-                },
-                ugli::DrawParameters {
-                    viewport: Some(viewport),
-                    ..default()
-                },
-            );
-            for p in [p1, p2] {
-                ugli::draw(
-                    framebuffer,
-                    &self.ctx.shaders.circle,
-                    ugli::DrawMode::TriangleFan,
-                    &*self.ctx.quad,
-                    ugli::uniforms! {
-                        u_projection_matrix: mat3::ortho(bb.map(|x| x as f32)),
-                        u_view_matrix: mat3::identity(),
-                        u_transform: mat3::translate(p) * mat3::scale_uniform(width / 2.0 + 0.5),
-                        u_color: color,
-                        u_radius: (width / 2.0) / (width / 2.0 + 0.5),
-                    },
-                    ugli::DrawParameters {
-                        viewport: Some(viewport),
-                        ..default()
-                    },
-                );
+        }
+        texture.write_pixels(area.min, &pixels);
+    }
+
+    fn paint_line(
+        &self,
+        stroke: &mut BrushStroke,
+        texture: &mut Texture,
+        p1: vec2<f32>,
+        p2: vec2<f32>,
+        color: Rgba<f32>,
+    ) {
+        let area = self.line_bounds(p1, p2);
+        stroke.edit.touch(texture, area);
+        self.cover_line(&mut stroke.coverage, p1, p2);
+        self.composite(texture, &stroke.edit, &stroke.coverage, area, color);
+    }
+}
+
+/// Pixels covered by a stroke, drawn on the cpu so that nothing is read back from the gpu
+#[derive(Default)]
+pub(super) struct Coverage {
+    pixels: HashSet<vec2<i32>>,
+}
+
+impl Coverage {
+    pub(super) fn contains(&self, pos: vec2<i32>) -> bool {
+        self.pixels.contains(&pos)
+    }
+
+    /// Covers the pixels in the area whose centers are inside
+    pub(super) fn fill(&mut self, area: Aabb2<i32>, inside: impl Fn(vec2<f32>) -> bool) {
+        for y in area.min.y..area.max.y {
+            for x in area.min.x..area.max.x {
+                let pos = vec2(x, y);
+                if inside(pos.map(|x| x as f32 + 0.5)) {
+                    self.pixels.insert(pos);
+                }
             }
-        });
+        }
     }
 
-    fn actual_color(&self, state: &State) -> Rgba<f32> {
-        if self.eraser {
-            Rgba::TRANSPARENT_BLACK
-        } else {
-            state.color
+    /// Covered pixels of the area in the color, the rest is transparent
+    pub(super) fn to_pixels(&self, area: Aabb2<i32>, color: Rgba<f32>) -> document::Pixels {
+        let mut pixels = document::Pixels::new(area.size().map(|x| x.max(0) as usize));
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                let pos = vec2(x, y);
+                if self.contains(area.min + pos.map(|x| x as i32)) {
+                    pixels.set(pos, color.convert());
+                }
+            }
         }
+        pixels
     }
 }
 
 pub struct BrushStroke {
+    plane: usize,
+    edit: TextureEdit,
+    /// Pixels covered by the stroke so far
+    coverage: Coverage,
     prev_draw_pos: vec2<f32>,
     sfx: geng::SoundEffect,
 }
@@ -115,32 +183,45 @@ impl Drop for BrushStroke {
 impl Tool for Brush {
     type Stroke = BrushStroke;
     fn start(&mut self, state: &mut State, ray: Ray) -> Option<BrushStroke> {
-        let color = self.actual_color(state);
-        if let Some(idx) = state.selected {
-            let plane = &mut state.model.planes[idx];
-            if let Some(raycast) = plane.raycast(ray) {
+        if let Some(idx) = state.selected.active() {
+            let transform = state.model.world_transform(idx);
+            let plane = &state.model.planes[idx];
+            if !plane.editable() {
+                return None;
+            }
+            if let Some(raycast) = plane.raycast(transform, ray) {
                 let pos = self.round_pos(raycast.texture_pos);
-                self.draw_line(&mut plane.texture, pos, pos, color);
-                return Some(BrushStroke {
+                let mut stroke = BrushStroke {
+                    plane: idx,
+                    edit: TextureEdit::new(),
+                    coverage: Coverage::default(),
                     prev_draw_pos: pos,
                     sfx: self.ctx.assets.scribble.play(),
-                });
+                };
+                let mut texture = plane.texture.borrow_mut();
+                self.paint_line(&mut stroke, &mut texture, pos, pos, state.color);
+                return Some(stroke);
             }
         }
         None
     }
     fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {
-        let color = self.actual_color(state);
-        if let Some(idx) = state.selected {
-            let plane = &mut state.model.planes[idx];
-            if let Some(raycast) = plane.raycast(ray) {
-                let pos = self.round_pos(raycast.texture_pos);
-                self.draw_line(&mut plane.texture, stroke.prev_draw_pos, pos, color);
-                stroke.prev_draw_pos = pos;
-            }
+        let transform = state.model.world_transform(stroke.plane);
+        let plane = &state.model.planes[stroke.plane];
+        if let Some(raycast) = plane.raycast(transform, ray) {
+            let pos = self.round_pos(raycast.texture_pos);
+            let mut texture = plane.texture.borrow_mut();
+            let prev = stroke.prev_draw_pos;
+            self.paint_line(stroke, &mut texture, prev, pos, state.color);
+            stroke.prev_draw_pos = pos;
+        }
+    }
+    fn end(&mut self, mut stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        let edit = std::mem::take(&mut stroke.edit);
+        if let Some(command) = Command::paint(&state.model, stroke.plane, edit) {
+            state.history.push(&state.model, command);
         }
     }
-    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {}
 
     fn draw(
         &mut self,
@@ -155,48 +236,41 @@ impl Tool for Brush {
 
         // Draw preview
         if let Some(ray) = ray {
-            if let Some(idx) = state.selected {
-                let plane = &state.model.planes[idx];
-
-                let mut preview_plane = Plane {
-                    texture: Texture::new(self.ctx.geng.ugli()),
-                    transform: plane.transform,
-                };
-
-                if let Some(raycast) = preview_plane.raycast(ray) {
-                    let pos = self.round_pos(raycast.texture_pos);
-                    self.draw_line(
-                        &mut preview_plane.texture,
-                        pos,
-                        pos,
-                        if self.eraser {
-                            Rgba::WHITE
-                        } else {
-                            state.color
-                        },
-                    );
-
-                    let offset = {
-                        const EPS: f32 = 1e-2;
-
-                        let forward = (state.camera.view_matrix().inverse()
-                            * vec4(0.0, 0.0, -1.0, 0.0))
-                        .xyz();
-                        let plane_up = (plane.transform * vec4(0.0, 0.0, 1.0, 0.0)).xyz();
-
-                        if vec3::dot(forward, plane_up) < 0.0 {
-                            EPS
-                        } else {
-                            -EPS
-                        }
+            if let Some(idx) = state.selected.active() {
+                let transform = state.model.world_transform(idx);
+                if let Some(raycast) = state.model.planes[idx].raycast(transform, ray) {
+                    let color = if self.eraser {
+                        Rgba::WHITE
+                    } else {
+                        state.color
                     };
-                    preview_plane.transform *= mat4::translate(vec3(0.0, 0.0, offset));
-                    if !self.eraser {
-                        self.ctx
-                            .draw_plane(&preview_plane, framebuffer, &state.camera);
+                    let origin = self.round_pos(vec2::ZERO);
+                    let key = (self.size, color);
+                    if self
+                        .preview
+                        .as_ref()
+                        .map_or(true, |(old, _plane)| *old != key)
+                    {
+                        let mut coverage = Coverage::default();
+                        self.cover_line(&mut coverage, origin, origin);
+                        let area = self.line_bounds(origin, origin);
+                        let texture = Texture::from_pixels(
+                            self.ctx.geng.ugli(),
+                            &coverage.to_pixels(area, color),
+                            area.min,
+                        );
+                        self.preview = Some((key, Plane::new(texture, mat4::identity())));
                     }
-                    self.ctx
-                        .draw_plane_outline(&preview_plane, framebuffer, &state.camera);
+                    let (_key, plane) = self.preview.as_ref().unwrap();
+                    // Both are rounded the same way, so the tip moves by whole pixels
+                    let offset = self.round_pos(raycast.texture_pos) - origin;
+                    self.ctx.draw_preview(
+                        plane,
+                        transform * mat4::translate(offset.extend(0.0)),
+                        framebuffer,
+                        &state.camera,
+                        !self.eraser,
+                    );
                 }
             }
         }
@@ -205,7 +279,14 @@ impl Tool for Brush {
             false => "brush",
             true => "eraser",
         };
-        let text = format!("{text} ({:.1} px)", self.size);
+        let mut text = format!("{text} ({:.1} px", self.size);
+        if self.opacity < 1.0 {
+            text += &format!(", {:.0}%", self.opacity * 100.0);
+        }
+        if !self.eraser && self.blend_mode != BlendMode::Normal {
+            text += &format!(", {}", self.blend_mode.name());
+        }
+        text += ")";
         let font = self.ctx.geng.default_font();
         let text_align = vec2::splat(geng::TextAlign::CENTER);
         let text_measure = font.measure(text.as_str(), text_align).unwrap();
@@ -240,6 +321,19 @@ impl Tool for Brush {
     fn handle_event(&mut self, event: geng::Event) {
         if let geng::Event::KeyPress { key } = event {
             let keys = &self.ctx.keys.brush;
+            let step = self.ctx.config.opacity_step;
+            // Opacity keys also match the size keys, since they only add a modifier
+            if keys.increase_opacity.matches(&event, &self.ctx) {
+                self.opacity = (self.opacity + step).min(1.0);
+                return;
+            }
+            if keys.decrease_opacity.matches(&event, &self.ctx) {
+                self.opacity = (self.opacity - step).max(step);
+                return;
+            }
+            if keys.blend_mode.matches(&event, &self.ctx) && !self.eraser {
+                self.blend_mode = self.blend_mode.next();
+            }
             if keys.decrease_size.matches(&event, &self.ctx) {
                 self.size = (self.size - 1).max(1);
             }
@@ -9,23 +9,13 @@ impl ColorPicker {
         Self { ctx: ctx.clone() }
     }
     fn find(&self, state: &State, ray: Ray) -> Option<Rgba<f32>> {
-        let mut closest = None;
-        for plane in &state.model.planes {
-            if let Some(raycast) = plane.raycast(ray) {
-                let color = plane.texture.color_at(raycast.texture_pos);
-                if color.a == 0.0 {
-                    continue;
-                }
-                let new = (raycast.t, color);
-                closest = match closest {
-                    Some(current) => {
-                        Some(std::cmp::min_by_key(current, new, |&(t, _color)| r32(t)))
-                    }
-                    None => Some(new),
-                };
-            }
-        }
-        closest.map(|(_t, color)| color)
+        let (idx, raycast) = state.model.raycast(ray, RaycastOptions::default())?;
+        Some(
+            state.model.planes[idx]
+                .texture
+                .borrow()
+                .color_at(raycast.texture_pos),
+        )
     }
 }
 
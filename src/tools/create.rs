@@ -9,34 +9,47 @@ impl Create {
         Self { ctx: ctx.clone() }
     }
     fn new_transform(&self, state: &State, ray: Ray) -> Option<mat4<f32>> {
-        Some(self.ctx.round_matrix({
-            let pos = match state.selected {
-                Some(idx) => {
-                    let plane = &state.model.planes[idx];
-                    let Some(raycast) = plane.raycast(ray) else {
-                        return None;
-                    };
-                    (plane.transform * raycast.texture_pos.extend(0.0).extend(1.0)).into_3d()
-                }
-                None => state.camera.pos,
-            };
-            let mut m = state.camera.view_matrix().inverse();
-            m[(0, 3)] = pos.x;
-            m[(1, 3)] = pos.y;
-            m[(2, 3)] = pos.z;
-            m
-        }))
+        new_plane_transform(&self.ctx, state, ray)
     }
 }
 
+/// Camera-facing transform at the point where the ray hits the active plane
+pub fn new_plane_transform(ctx: &Ctx, state: &State, ray: Ray) -> Option<mat4<f32>> {
+    let pos = match state.selected.active() {
+        Some(idx) => {
+            let transform = state.model.world_transform(idx);
+            let Some(raycast) = state.model.planes[idx].raycast(transform, ray) else {
+                return None;
+            };
+            (transform * raycast.texture_pos.extend(0.0).extend(1.0)).into_3d()
+        }
+        None => state.camera.pos,
+    };
+    Some(plane_transform_at(ctx, state, pos))
+}
+
+pub fn plane_transform_at(ctx: &Ctx, state: &State, pos: vec3<f32>) -> mat4<f32> {
+    ctx.round_matrix({
+        let mut m = state.camera.view_matrix().inverse();
+        m[(0, 3)] = pos.x;
+        m[(1, 3)] = pos.y;
+        m[(2, 3)] = pos.z;
+        m
+    })
+}
+
 impl Tool for Create {
     type Stroke = ();
     fn start(&mut self, state: &mut State, ray: Ray) -> Option<()> {
-        state.model.planes.push(Plane {
-            texture: Texture::new(self.ctx.geng.ugli()),
-            transform: self.new_transform(state, ray)?,
-        });
-        state.selected = Some(state.model.planes.len() - 1);
+        state.model.planes.push(Plane::new(
+            Texture::new(self.ctx.geng.ugli()),
+            self.new_transform(state, ray)?,
+        ));
+        let index = state.model.planes.len() - 1;
+        state
+            .history
+            .push(&state.model, Command::Create { index, plane: None });
+        state.selected = Selection::single(index);
         None
     }
     fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {}
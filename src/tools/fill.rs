@@ -0,0 +1,251 @@
+use super::*;
+
+use std::collections::VecDeque;
+
+pub struct Fill {
+    ctx: Ctx,
+    /// Largest difference of a color channel that still counts as the same color
+    tolerance: f32,
+    /// Spread to diagonal neighbours too, 8-connectivity instead of 4
+    diagonal: bool,
+    /// Keep inside of the bounds of what is already drawn on the plane
+    bounded: bool,
+    /// Whether clicking a transparent pixel fills the transparent region around it
+    fill_transparent: bool,
+}
+
+impl Fill {
+    pub fn new(ctx: &Ctx) -> Self {
+        let config = &ctx.config.fill;
+        Self {
+            ctx: ctx.clone(),
+            tolerance: config.tolerance,
+            diagonal: config.diagonal,
+            bounded: config.bounded,
+            fill_transparent: config.fill_transparent,
+        }
+    }
+
+    /// Area the fill can spread in, none if it can not start at the given pixel
+    fn area(&self, texture: &Texture, start: vec2<i32>) -> Option<Aabb2<i32>> {
+        let contains = |bb: Aabb2<i32>| {
+            bb.min.x <= start.x && start.x < bb.max.x && bb.min.y <= start.y && start.y < bb.max.y
+        };
+        let pixel = Aabb2::point(start).extend_positive(vec2::splat(1));
+        match texture.bounding_box() {
+            Some(bb) if self.bounded => contains(bb).then_some(bb),
+            None if self.bounded => None,
+            // Open transparent regions would go on forever
+            bb => {
+                let bb = bb.map_or(pixel, |bb| Aabb2 {
+                    min: bb.min.zip(pixel.min).map(|(a, b)| a.min(b)),
+                    max: bb.max.zip(pixel.max).map(|(a, b)| a.max(b)),
+                });
+                Some(bb.extend_uniform(self.ctx.config.fill.max_distance as i32))
+            }
+        }
+    }
+
+    /// Pixels connected to the start that have the same color as it,
+    /// with diagonal ones being connected too when asked
+    fn region(
+        pixels: &document::Pixels,
+        start: vec2<usize>,
+        tolerance: f32,
+        diagonal: bool,
+    ) -> Vec<vec2<usize>> {
+        // Fully transparent pixels are all the same, whatever their rgb is
+        let channels = |color: Rgba<u8>| -> [i32; 4] {
+            if color.a == 0 {
+                [0; 4]
+            } else {
+                [color.r, color.g, color.b, color.a].map(i32::from)
+            }
+        };
+        let target = channels(pixels.get(start));
+        let tolerance = (tolerance * 255.0).round() as i32;
+        let matches = |pos: vec2<usize>| {
+            let color = channels(pixels.get(pos));
+            (0..4).all(|i| (color[i] - target[i]).abs() <= tolerance)
+        };
+
+        let mut neighbours = vec![vec2(-1, 0), vec2(1, 0), vec2(0, -1), vec2(0, 1)];
+        if diagonal {
+            neighbours.extend([vec2(-1, -1), vec2(-1, 1), vec2(1, -1), vec2(1, 1)]);
+        }
+        let size = pixels.size.map(|x| x as i32);
+        let mut visited = vec![false; pixels.size.x * pixels.size.y];
+        let mut region = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        visited[start.y * pixels.size.x + start.x] = true;
+        while let Some(pos) = queue.pop_front() {
+            region.push(pos);
+            for &delta in &neighbours {
+                let next = pos.map(|x| x as i32) + delta;
+                if next.x < 0 || next.y < 0 || next.x >= size.x || next.y >= size.y {
+                    continue;
+                }
+                let next = next.map(|x| x as usize);
+                let index = next.y * pixels.size.x + next.x;
+                if !visited[index] && matches(next) {
+                    visited[index] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        region
+    }
+}
+
+impl Tool for Fill {
+    type Stroke = ();
+    fn start(&mut self, state: &mut State, ray: Ray) -> Option<()> {
+        let idx = state.selected.active()?;
+        let plane = &state.model.planes[idx];
+        if !plane.editable() {
+            return None;
+        }
+        let raycast = plane.raycast(state.model.world_transform(idx), ray)?;
+        let start = raycast.texture_pos.map(|x| x.floor() as i32);
+        let mut texture = plane.texture.borrow_mut();
+        if !self.fill_transparent && texture.color_at(raycast.texture_pos).a == 0.0 {
+            return None;
+        }
+        let area = self.area(&texture, start)?;
+        let mut pixels = texture.read_pixels(area);
+        let region = Self::region(
+            &pixels,
+            (start - area.min).map(|x| x as usize),
+            self.tolerance,
+            self.diagonal,
+        );
+
+        let color: Rgba<u8> = state.color.convert();
+        for &pos in &region {
+            pixels.set(pos, color);
+        }
+        let filled = Aabb2::points_bounding_box(region)?.extend_positive(vec2::splat(1));
+        let rect = filled.map_bounds(|p| area.min + p.map(|x| x as i32));
+        let mut edit = TextureEdit::new();
+        edit.touch(&texture, rect);
+        texture.write_pixels(rect.min, &pixels.sub(filled));
+        drop(texture);
+
+        if let Some(command) = Command::paint(&state.model, idx, edit) {
+            state.history.push(&state.model, command);
+        }
+        None
+    }
+    fn resume(&mut self, _stroke: &mut Self::Stroke, _state: &mut State, _ray: Ray) {}
+    fn end(&mut self, _stroke: Self::Stroke, _state: &mut State, _ray: Ray) {}
+
+    fn draw(
+        &mut self,
+        framebuffer: &mut ugli::Framebuffer,
+        _ray: Option<Ray>,
+        _stroke: Option<&mut Self::Stroke>,
+        state: &mut State,
+        ui_camera: &dyn AbstractCamera2d,
+        status_pos: mat3<f32>,
+    ) {
+        let mut text = format!(
+            "fill ({:.0}%, {}",
+            self.tolerance * 100.0,
+            if self.diagonal { "8-way" } else { "4-way" },
+        );
+        if self.bounded {
+            text += ", bounded";
+        }
+        if self.fill_transparent {
+            text += ", transparent";
+        }
+        text += ")";
+        self.ctx.geng.default_font().draw(
+            framebuffer,
+            ui_camera,
+            &text,
+            vec2::splat(geng::TextAlign::CENTER),
+            status_pos,
+            state.color,
+        );
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        let keys = &self.ctx.keys.fill;
+        let step = self.ctx.config.fill.tolerance_step;
+        if keys.increase_tolerance.matches(&event, &self.ctx) {
+            self.tolerance = (self.tolerance + step).min(1.0);
+        }
+        if keys.decrease_tolerance.matches(&event, &self.ctx) {
+            self.tolerance = (self.tolerance - step).max(0.0);
+        }
+        if keys.connectivity.matches(&event, &self.ctx) {
+            self.diagonal = !self.diagonal;
+        }
+        if keys.bounded.matches(&event, &self.ctx) {
+            self.bounded = !self.bounded;
+        }
+        if keys.transparent.matches(&event, &self.ctx) {
+            self.fill_transparent = !self.fill_transparent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows are given top to bottom, '#' is opaque black, '.' is transparent
+    fn pixels(rows: &[&str]) -> document::Pixels {
+        let mut pixels = document::Pixels::new(vec2(rows[0].len(), rows.len()));
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    pixels.set(vec2(x, y), Rgba::BLACK);
+                }
+            }
+        }
+        pixels
+    }
+
+    fn sorted(region: Vec<vec2<usize>>) -> Vec<(usize, usize)> {
+        let mut region: Vec<(usize, usize)> =
+            region.into_iter().map(|pos| (pos.x, pos.y)).collect();
+        region.sort();
+        region
+    }
+
+    #[test]
+    fn region_stops_at_other_colors() {
+        let pixels = pixels(&["..#", "##.", "..."]);
+        let region = Fill::region(&pixels, vec2(0, 2), 0.0, false);
+        assert_eq!(sorted(region), [(0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn region_goes_through_diagonals_when_asked() {
+        let pixels = pixels(&["#..", ".#.", "..#"]);
+        assert_eq!(Fill::region(&pixels, vec2(0, 2), 0.0, false).len(), 1);
+        let region = Fill::region(&pixels, vec2(0, 2), 0.0, true);
+        assert_eq!(sorted(region), [(0, 2), (1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn region_uses_tolerance() {
+        let mut pixels = document::Pixels::new(vec2(3, 1));
+        pixels.set(vec2(0, 0), Rgba::new(100, 100, 100, 255));
+        pixels.set(vec2(1, 0), Rgba::new(110, 100, 100, 255));
+        pixels.set(vec2(2, 0), Rgba::new(140, 100, 100, 255));
+        assert_eq!(Fill::region(&pixels, vec2(0, 0), 0.0, false).len(), 1);
+        assert_eq!(Fill::region(&pixels, vec2(0, 0), 0.05, false).len(), 2);
+        // Compared to the start pixel, not to the neighbour it spread from
+        assert_eq!(Fill::region(&pixels, vec2(0, 0), 0.1, false).len(), 2);
+    }
+
+    #[test]
+    fn transparent_pixels_match_whatever_their_rgb() {
+        let mut pixels = document::Pixels::new(vec2(2, 1));
+        pixels.set(vec2(1, 0), Rgba::new(255, 0, 0, 0));
+        assert_eq!(Fill::region(&pixels, vec2(0, 0), 0.0, false).len(), 2);
+    }
+}
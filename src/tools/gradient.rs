@@ -0,0 +1,312 @@
+use super::*;
+
+use pixel_selection::Mask;
+
+/// Thresholds of a 4x4 ordered dither, in sixteenths
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Most pixels on a side of the preview, bigger areas are previewed at a lower resolution
+/// and only drawn in full when the stroke ends
+const PREVIEW_SIZE: i32 = 256;
+
+pub struct Gradient {
+    ctx: Ctx,
+    /// Around the start point instead of along the line
+    radial: bool,
+    /// Fades the current color out instead of blending into the secondary one
+    to_transparent: bool,
+    /// Every pixel gets one of the two colors, picked by an ordered dither
+    dither: bool,
+}
+
+pub struct GradientStroke {
+    plane: usize,
+    /// Pixel selection on the plane at the start of the stroke
+    selection: Option<Mask>,
+    start: vec2<f32>,
+    end: vec2<f32>,
+    /// Plane pixels with the gradient drawn over them, at a lower resolution for big areas
+    preview: Plane,
+    /// From preview pixels to plane pixels
+    preview_transform: mat4<f32>,
+}
+
+impl Gradient {
+    pub fn new(ctx: &Ctx) -> Self {
+        let config = &ctx.config.gradient;
+        Self {
+            ctx: ctx.clone(),
+            radial: config.radial,
+            to_transparent: config.to_transparent,
+            dither: config.dither,
+        }
+    }
+
+    fn selection(&self, state: &State, plane: usize) -> Option<Mask> {
+        let selection = state.pixel_selection.as_ref()?;
+        (selection.plane == plane).then(|| selection.mask.clone())
+    }
+
+    /// Area of the pixel selection when it is on the plane, otherwise the allocated tiles
+    /// and the tiles under the drag, so it also works on an empty plane
+    ///
+    /// Without a selection transparent pixels get the gradient too, like a fill of the whole plane
+    fn area(&self, stroke: &GradientStroke, state: &State) -> Aabb2<i32> {
+        if let Some(selection) = &stroke.selection {
+            return selection.area;
+        }
+        let drag = Aabb2::points_bounding_box([stroke.start, stroke.end])
+            .unwrap()
+            .map_bounds(|p| p.map(|x| x.floor() as i32))
+            .extend_positive(vec2::splat(1));
+        let mut area = Texture::tile_aligned(drag);
+        if let Some(allocated) = state.model.planes[stroke.plane]
+            .texture
+            .borrow()
+            .allocated_bounds()
+        {
+            area = Aabb2 {
+                min: area.min.zip(allocated.min).map(|(a, b)| a.min(b)),
+                max: area.max.zip(allocated.max).map(|(a, b)| a.max(b)),
+            };
+        }
+        area
+    }
+
+    fn contains(&self, stroke: &GradientStroke, pos: vec2<i32>) -> bool {
+        stroke
+            .selection
+            .as_ref()
+            .map_or(true, |selection| selection.contains(pos))
+    }
+
+    fn end_color(&self, state: &State) -> Rgba<f32> {
+        if self.to_transparent {
+            Rgba {
+                a: 0.0,
+                ..state.color
+            }
+        } else {
+            state.secondary_color
+        }
+    }
+
+    /// How far the pixel is from the start color to the end color, from 0 to 1
+    fn progress(&self, start: vec2<f32>, end: vec2<f32>, pos: vec2<f32>) -> f32 {
+        let delta = end - start;
+        let len_sqr = vec2::dot(delta, delta);
+        if len_sqr == 0.0 {
+            return 0.0;
+        }
+        let t = if self.radial {
+            (pos - start).len() / len_sqr.sqrt()
+        } else {
+            vec2::dot(pos - start, delta) / len_sqr
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    fn color(&self, from: Rgba<f32>, to: Rgba<f32>, t: f32, pos: vec2<i32>) -> Rgba<f32> {
+        if self.dither {
+            let threshold = BAYER[pos.y.rem_euclid(4) as usize][pos.x.rem_euclid(4) as usize];
+            return if t * 16.0 > threshold as f32 + 0.5 {
+                to
+            } else {
+                from
+            };
+        }
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Rgba::new(
+            lerp(from.r, to.r),
+            lerp(from.g, to.g),
+            lerp(from.b, to.b),
+            lerp(from.a, to.a),
+        )
+    }
+
+    /// Same as [Gradient::render], but sampled once per block of pixels,
+    /// with blocks big enough for the preview to fit in [PREVIEW_SIZE]
+    fn render_preview(&self, stroke: &mut GradientStroke, state: &State) {
+        let texture = state.model.planes[stroke.plane].texture.borrow();
+        let area = self.area(stroke, state);
+        let scale = ((area.width().max(area.height()) + PREVIEW_SIZE - 1) / PREVIEW_SIZE).max(1);
+        let mut pixels = document::Pixels::new(
+            area.size()
+                .map(|x| (x.max(0) + scale - 1) / scale)
+                .map(|x| x as usize),
+        );
+        let (from, to) = (state.color, self.end_color(state));
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                let pixel = vec2(x, y);
+                let block = pixel.map(|x| x as i32);
+                let pos = (area.min + block * scale + vec2::splat(scale / 2))
+                    .zip(area.max)
+                    .map(|(a, max)| a.min(max - 1));
+                if !self.contains(stroke, pos) {
+                    continue;
+                }
+                let center = pos.map(|x| x as f32 + 0.5);
+                let t = self.progress(stroke.start, stroke.end, center);
+                let color = blend::over(texture.color_at(center), self.color(from, to, t, block));
+                pixels.set(pixel, color.convert());
+            }
+        }
+        stroke.preview = Plane::new(
+            Texture::from_pixels(self.ctx.geng.ugli(), &pixels, vec2::ZERO),
+            mat4::identity(),
+        );
+        stroke.preview_transform = mat4::translate(area.min.map(|x| x as f32).extend(0.0))
+            * mat4::scale(vec3(scale as f32, scale as f32, 1.0));
+    }
+
+    /// Plane pixels in the area with the gradient drawn over them
+    fn render(&self, stroke: &GradientStroke, state: &State, area: Aabb2<i32>) -> document::Pixels {
+        let mut pixels = state.model.planes[stroke.plane]
+            .texture
+            .borrow()
+            .read_pixels(area);
+        let (from, to) = (state.color, self.end_color(state));
+        for y in 0..pixels.size.y {
+            for x in 0..pixels.size.x {
+                let pixel = vec2(x, y);
+                let pos = area.min + pixel.map(|x| x as i32);
+                if !self.contains(stroke, pos) {
+                    continue;
+                }
+                let t = self.progress(stroke.start, stroke.end, pos.map(|x| x as f32 + 0.5));
+                let color = blend::over(pixels.get(pixel).convert(), self.color(from, to, t, pos));
+                pixels.set(pixel, color.convert());
+            }
+        }
+        pixels
+    }
+}
+
+impl Tool for Gradient {
+    type Stroke = GradientStroke;
+    fn start(&mut self, state: &mut State, ray: Ray) -> Option<GradientStroke> {
+        let idx = state.selected.active()?;
+        let plane = &state.model.planes[idx];
+        if !plane.editable() {
+            return None;
+        }
+        let raycast = plane.raycast(state.model.world_transform(idx), ray)?;
+        let pos = self.ctx.round_pos(raycast.texture_pos);
+        let mut stroke = GradientStroke {
+            plane: idx,
+            selection: self.selection(state, idx),
+            start: pos,
+            end: pos,
+            preview: Plane::new(Texture::new(self.ctx.geng.ugli()), mat4::identity()),
+            preview_transform: mat4::identity(),
+        };
+        self.render_preview(&mut stroke, state);
+        Some(stroke)
+    }
+    fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {
+        let plane = &state.model.planes[stroke.plane];
+        let Some(raycast) = plane.raycast(state.model.world_transform(stroke.plane), ray) else {
+            return;
+        };
+        let pos = self.ctx.round_pos(raycast.texture_pos);
+        if pos != stroke.end {
+            stroke.end = pos;
+            self.render_preview(stroke, state);
+        }
+    }
+    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        let Some(plane) = state.model.planes.get(stroke.plane) else {
+            return;
+        };
+        let area = self.area(&stroke, state);
+        let pixels = self.render(&stroke, state, area);
+        let mut texture = plane.texture.borrow_mut();
+        let mut edit = TextureEdit::new();
+        edit.touch(&texture, area);
+        texture.write_pixels(area.min, &pixels);
+        drop(texture);
+        if let Some(command) = Command::paint(&state.model, stroke.plane, edit) {
+            state.history.push(&state.model, command);
+        }
+    }
+
+    fn draw(
+        &mut self,
+        framebuffer: &mut ugli::Framebuffer,
+        _ray: Option<Ray>,
+        stroke: Option<&mut Self::Stroke>,
+        state: &mut State,
+        ui_camera: &dyn AbstractCamera2d,
+        status_pos: mat3<f32>,
+    ) {
+        if let Some(stroke) = stroke {
+            self.ctx.draw_preview(
+                &stroke.preview,
+                state.model.world_transform(stroke.plane) * stroke.preview_transform,
+                framebuffer,
+                &state.camera,
+                true,
+            );
+        }
+
+        let mut text = format!(
+            "gradient ({}",
+            if self.radial { "radial" } else { "linear" },
+        );
+        if self.to_transparent {
+            text += ", to transparent";
+        }
+        if self.dither {
+            text += ", dithered";
+        }
+        text += ")";
+        let font = self.ctx.geng.default_font();
+        let text_align = vec2::splat(geng::TextAlign::CENTER);
+        let text_measure = font.measure(&text, text_align).unwrap();
+        let framebuffer_size = framebuffer.size().map(|x| x as f32);
+        for (offset, color) in [
+            (-text_measure.max.x - 1.5, state.color),
+            (text_measure.max.x + 1.5, self.end_color(state)),
+        ] {
+            ugli::draw(
+                framebuffer,
+                &self.ctx.shaders.color_2d,
+                ugli::DrawMode::TriangleFan,
+                &*self.ctx.quad,
+                (
+                    ugli::uniforms! {
+                        u_transform: status_pos * mat3::translate(vec2(offset, 0.0)),
+                        u_color: color,
+                    },
+                    ui_camera.uniforms(framebuffer_size),
+                ),
+                ugli::DrawParameters {
+                    blend_mode: Some(ugli::BlendMode::straight_alpha()),
+                    ..default()
+                },
+            );
+        }
+        font.draw(
+            framebuffer,
+            ui_camera,
+            &text,
+            text_align,
+            status_pos,
+            Rgba::WHITE,
+        );
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        let keys = &self.ctx.keys.gradient;
+        if keys.radial.matches(&event, &self.ctx) {
+            self.radial = !self.radial;
+        }
+        if keys.transparent.matches(&event, &self.ctx) {
+            self.to_transparent = !self.to_transparent;
+        }
+        if keys.dither.matches(&event, &self.ctx) {
+            self.dither = !self.dither;
+        }
+    }
+}
@@ -2,12 +2,20 @@ use super::*;
 
 mod brush;
 mod color_picker;
-mod create;
+pub mod create;
+mod fill;
+mod gradient;
 mod pick;
+mod select;
+mod shape;
 mod transform;
 
 pub use brush::Brush;
 pub use color_picker::ColorPicker;
 pub use create::Create;
+pub use fill::Fill;
+pub use gradient::Gradient;
 pub use pick::Pick;
+pub use select::{Select, SelectMode};
+pub use shape::{Shape, ShapeKind};
 pub use transform::Transform;
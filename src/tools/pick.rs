@@ -1,54 +1,143 @@
 use super::*;
 
+/// Cursor has to move further than this many pixels to start a box selection
+const DRAG_THRESHOLD: f32 = 4.0;
+
 pub struct Pick {
     ctx: Ctx,
 }
 
+pub struct PickStroke {
+    start: vec2<f32>,
+}
+
 impl Pick {
     pub fn new(ctx: &Ctx) -> Self {
         Self { ctx: ctx.clone() }
     }
     fn find(&self, state: &State, ray: Ray) -> Option<usize> {
-        let mut closest = None;
-        for (idx, plane) in state.model.planes.iter().enumerate() {
-            if let Some(raycast) = plane.raycast(ray) {
-                if plane.texture.color_at(raycast.texture_pos).a == 0.0 {
-                    continue;
-                }
-                let new = (raycast.t, idx);
-                closest = match closest {
-                    Some(current) => Some(std::cmp::min_by_key(current, new, |&(t, _idx)| r32(t))),
-                    None => Some(new),
+        state
+            .model
+            .raycast(ray, RaycastOptions::default())
+            .map(|(idx, _raycast)| idx)
+    }
+    fn cursor_pos(&self) -> vec2<f32> {
+        self.ctx
+            .geng
+            .window()
+            .cursor_position()
+            .unwrap_or(vec2::ZERO)
+            .map(|x| x as f32)
+    }
+    fn adding(&self) -> bool {
+        self.ctx
+            .geng
+            .window()
+            .is_key_pressed(self.ctx.keys.selection.toggle)
+    }
+    /// Visible planes whose projected bounds intersect the screen space box
+    fn find_in_box(&self, state: &State, area: Aabb2<f32>) -> Vec<usize> {
+        let framebuffer_size = self.ctx.geng.window().size().map(|x| x as f32);
+        state
+            .model
+            .planes
+            .iter()
+            .enumerate()
+            .filter(|(_idx, plane)| plane.visible)
+            .filter(|&(idx, plane)| {
+                let Some(bb) = plane.texture.borrow().bounding_box() else {
+                    return false;
                 };
-            }
-        }
-        closest.map(|(_t, idx)| idx)
+                let bb = bb.map(|x| x as f32);
+                let transform = state.model.world_transform(idx);
+                let corners: Vec<vec2<f32>> = bb
+                    .corners()
+                    .into_iter()
+                    .filter_map(|corner| {
+                        let pos = (transform * corner.extend(0.0).extend(1.0)).into_3d();
+                        state.camera.world_to_screen(framebuffer_size, pos)
+                    })
+                    .collect();
+                let Some(screen_bb) = Aabb2::points_bounding_box(corners) else {
+                    return false;
+                };
+                screen_bb.max.x >= area.min.x
+                    && screen_bb.min.x <= area.max.x
+                    && screen_bb.max.y >= area.min.y
+                    && screen_bb.min.y <= area.max.y
+            })
+            .map(|(idx, _plane)| idx)
+            .collect()
     }
 }
 
 impl Tool for Pick {
-    type Stroke = ();
-    fn start(&mut self, state: &mut State, ray: Ray) -> Option<()> {
-        state.selected = self.find(state, ray);
-        None
+    type Stroke = PickStroke;
+    fn start(&mut self, _state: &mut State, _ray: Ray) -> Option<PickStroke> {
+        Some(PickStroke {
+            start: self.cursor_pos(),
+        })
     }
     fn resume(&mut self, _stroke: &mut Self::Stroke, _state: &mut State, _ray: Ray) {}
-    fn end(&mut self, _stroke: Self::Stroke, _state: &mut State, _ray: Ray) {}
+    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        let end = self.cursor_pos();
+        if (end - stroke.start).len() < DRAG_THRESHOLD {
+            let found = self.find(state, ray);
+            if self.adding() {
+                if let Some(idx) = found {
+                    state.selected.toggle(idx);
+                }
+            } else {
+                state.selected = found.map_or_else(Selection::new, Selection::single);
+            }
+        } else {
+            if !self.adding() {
+                state.selected.clear();
+            }
+            for idx in self.find_in_box(state, Aabb2::from_corners(stroke.start, end)) {
+                state.selected.add(idx);
+            }
+        }
+    }
 
     fn draw(
         &mut self,
         framebuffer: &mut ugli::Framebuffer,
         ray: Option<Ray>,
-        _stroke: Option<&mut Self::Stroke>,
+        stroke: Option<&mut Self::Stroke>,
         state: &mut State,
         _ui_camera: &dyn AbstractCamera2d,
         _status_pos: mat3<f32>,
     ) {
+        if let Some(stroke) = stroke {
+            let end = self.cursor_pos();
+            if (end - stroke.start).len() < DRAG_THRESHOLD {
+                return;
+            }
+            let area = Aabb2::from_corners(stroke.start, end);
+            for idx in self.find_in_box(state, area) {
+                self.ctx.draw_plane_outline(
+                    &state.model.planes[idx],
+                    state.model.world_transform(idx),
+                    framebuffer,
+                    &state.camera,
+                );
+            }
+            self.ctx.geng.draw2d().draw2d(
+                framebuffer,
+                &geng::PixelPerfectCamera,
+                &draw2d::Quad::new(area, Rgba::new(1.0, 1.0, 1.0, 0.2)),
+            );
+            return;
+        }
         let Some(ray) = ray else { return };
         if let Some(idx) = self.find(state, ray) {
-            let plane = &state.model.planes[idx];
-            self.ctx
-                .draw_plane_outline(plane, framebuffer, &state.camera);
+            self.ctx.draw_plane_outline(
+                &state.model.planes[idx],
+                state.model.world_transform(idx),
+                framebuffer,
+                &state.camera,
+            );
         }
     }
 }
@@ -0,0 +1,201 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Between the pixel corners closest to the start and end points
+    Rectangle,
+    /// Freehand outline that is closed back to the start
+    Lasso,
+}
+
+pub struct Select {
+    ctx: Ctx,
+    mode: SelectMode,
+}
+
+pub enum SelectStroke {
+    /// Outline of a new selection in plane pixel space
+    Select {
+        plane: usize,
+        points: Vec<vec2<f32>>,
+    },
+    /// Floating pixels follow the point they were grabbed by
+    Move {
+        plane: usize,
+        grab: vec2<f32>,
+        start_pos: vec2<i32>,
+    },
+}
+
+impl Select {
+    pub fn new(ctx: &Ctx, mode: SelectMode) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            mode,
+        }
+    }
+
+    /// Lasso follows the cursor exactly, rectangles go along the pixel edges
+    fn round_pos(&self, pos: vec2<f32>) -> vec2<f32> {
+        match self.mode {
+            SelectMode::Rectangle => pos.map(|x| x.round()),
+            SelectMode::Lasso => pos,
+        }
+    }
+
+    fn mask(&self, points: &[vec2<f32>]) -> pixel_selection::Mask {
+        match self.mode {
+            SelectMode::Rectangle => {
+                let area = Aabb2::points_bounding_box(points.iter().copied())
+                    .unwrap()
+                    .map(|x| x as i32);
+                pixel_selection::Mask::rect(area)
+            }
+            SelectMode::Lasso => pixel_selection::Mask::polygon(points),
+        }
+    }
+
+    /// Outline of the selection while it is dragged, the mask is only made at the end
+    fn outline(&self, points: &[vec2<f32>]) -> Vec<vec2<f32>> {
+        match self.mode {
+            SelectMode::Rectangle => {
+                let area = Aabb2::points_bounding_box(points.iter().copied())
+                    .unwrap()
+                    .map(|x| x as i32 as f32);
+                area.corners().to_vec()
+            }
+            SelectMode::Lasso => points.to_vec(),
+        }
+    }
+
+    fn raycast(&self, state: &State, plane: usize, ray: Ray) -> Option<vec2<f32>> {
+        let raycast = state.model.planes[plane].raycast(state.model.world_transform(plane), ray)?;
+        Some(raycast.texture_pos)
+    }
+
+    fn start_move(&self, state: &State, ray: Ray) -> Option<SelectStroke> {
+        let floating = state.floating.as_ref()?;
+        let plane = floating.plane;
+        let grab = self.raycast(state, plane, ray)?;
+        let area = floating.area();
+        let inside = area.min.x as f32 <= grab.x
+            && grab.x < area.max.x as f32
+            && area.min.y as f32 <= grab.y
+            && grab.y < area.max.y as f32;
+        inside.then_some(SelectStroke::Move {
+            plane,
+            grab,
+            start_pos: floating.pos,
+        })
+    }
+}
+
+impl Tool for Select {
+    type Stroke = SelectStroke;
+    fn start(&mut self, state: &mut State, ray: Ray) -> Option<SelectStroke> {
+        // Floating pixels are dragged on whichever plane they are
+        if let Some(stroke) = self.start_move(state, ray) {
+            return Some(stroke);
+        }
+        let idx = state.selected.active()?;
+        let pos = self.raycast(state, idx, ray)?;
+
+        // Dragging the selection lifts the pixels off the plane
+        let grabbed = state.pixel_selection.as_ref().map_or(false, |selection| {
+            selection.plane == idx && selection.mask.contains(pos.map(|x| x.floor() as i32))
+        });
+        if grabbed && state.model.planes[idx].editable() {
+            state.lift_selection(self.ctx.geng.ugli());
+            return self.start_move(state, ray);
+        }
+
+        state.commit_floating();
+        state.pixel_selection = None;
+        Some(SelectStroke::Select {
+            plane: idx,
+            points: vec![self.round_pos(pos)],
+        })
+    }
+    fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {
+        match stroke {
+            SelectStroke::Select { plane, points } => {
+                let Some(pos) = self.raycast(state, *plane, ray) else {
+                    return;
+                };
+                let pos = self.round_pos(pos);
+                match self.mode {
+                    SelectMode::Rectangle => {
+                        points.truncate(1);
+                        points.push(pos);
+                    }
+                    SelectMode::Lasso => {
+                        if (pos - *points.last().unwrap()).len() >= 1.0 {
+                            points.push(pos);
+                        }
+                    }
+                }
+            }
+            SelectStroke::Move {
+                plane,
+                grab,
+                start_pos,
+            } => {
+                let Some(pos) = self.raycast(state, *plane, ray) else {
+                    return;
+                };
+                if let Some(floating) = &mut state.floating {
+                    floating.pos = *start_pos + (pos - *grab).map(|x| x.round() as i32);
+                }
+            }
+        }
+    }
+    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        // Moving only changes the floating pixels, they are put down when committed
+        let SelectStroke::Select { plane, points } = stroke else {
+            return;
+        };
+        if plane >= state.model.planes.len() {
+            return;
+        }
+        // Clicking without dragging just deselects
+        let mask = self.mask(&points);
+        if !mask.is_empty() {
+            state.pixel_selection = Some(PixelSelection::new(self.ctx.geng.ugli(), plane, mask));
+        }
+    }
+
+    fn draw(
+        &mut self,
+        framebuffer: &mut ugli::Framebuffer,
+        _ray: Option<Ray>,
+        stroke: Option<&mut Self::Stroke>,
+        state: &mut State,
+        ui_camera: &dyn AbstractCamera2d,
+        status_pos: mat3<f32>,
+    ) {
+        if let Some(SelectStroke::Select { plane, points }) = stroke {
+            self.ctx.draw_polygon_outline(
+                &self.outline(points),
+                state.model.world_transform(*plane),
+                framebuffer,
+                &state.camera,
+            );
+        }
+
+        let mut text = match self.mode {
+            SelectMode::Rectangle => "select".to_owned(),
+            SelectMode::Lasso => "lasso select".to_owned(),
+        };
+        if state.floating.is_some() {
+            text += " (floating)";
+        }
+        self.ctx.geng.default_font().draw(
+            framebuffer,
+            ui_camera,
+            &text,
+            vec2::splat(geng::TextAlign::CENTER),
+            status_pos,
+            Rgba::WHITE,
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use super::*;
+
+use brush::Coverage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeKind {
+    Line,
+    /// Axis aligned in plane space, between the start and end corners
+    Rectangle,
+    /// Inscribed into the rectangle
+    Ellipse,
+}
+
+pub struct Shape {
+    ctx: Ctx,
+    /// Used for the outline and the size controls
+    brush: Brush,
+    kind: ShapeKind,
+    filled: bool,
+    /// Last preview along with what it was drawn for, rebuilt only when that changes
+    preview: Option<(PreviewKey, Plane)>,
+}
+
+/// Start, end, color, whether filled and the brush width
+type PreviewKey = (vec2<f32>, vec2<f32>, Rgba<f32>, bool, f32);
+
+pub struct ShapeStroke {
+    plane: usize,
+    start: vec2<f32>,
+    end: vec2<f32>,
+}
+
+impl Shape {
+    pub fn new(ctx: &Ctx, kind: ShapeKind) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            brush: Brush::new(ctx),
+            kind,
+            filled: false,
+            preview: None,
+        }
+    }
+
+    /// Snaps to the precision mode, then to where the brush lines up with the pixels
+    fn round_pos(&self, pos: vec2<f32>) -> vec2<f32> {
+        self.brush.round_pos(self.ctx.round_pos(pos))
+    }
+
+    fn bounds(&self, start: vec2<f32>, end: vec2<f32>) -> Aabb2<i32> {
+        self.brush.line_bounds(start, end)
+    }
+
+    fn cover_shape(&self, coverage: &mut Coverage, start: vec2<f32>, end: vec2<f32>) {
+        let rect = Aabb2::from_corners(start, end);
+        let outline: Vec<vec2<f32>> = match self.kind {
+            ShapeKind::Line => vec![start, end],
+            ShapeKind::Rectangle => {
+                let mut corners = rect.corners().to_vec();
+                corners.push(corners[0]);
+                corners
+            }
+            ShapeKind::Ellipse => {
+                let radius = rect.size() / 2.0;
+                // Segments about two pixels long, which is smooth enough for any brush size
+                let segments = ((radius.x + radius.y) * f32::PI / 2.0).ceil().max(8.0) as usize;
+                (0..=segments)
+                    .map(|i| {
+                        let (sin, cos) = (2.0 * f32::PI * i as f32 / segments as f32).sin_cos();
+                        rect.center() + vec2(cos * radius.x, sin * radius.y)
+                    })
+                    .collect()
+            }
+        };
+        let area = self.bounds(start, end);
+        if self.filled {
+            match self.kind {
+                ShapeKind::Line => {}
+                ShapeKind::Rectangle => coverage.fill(area, |pos| {
+                    rect.min.x <= pos.x
+                        && pos.x <= rect.max.x
+                        && rect.min.y <= pos.y
+                        && pos.y <= rect.max.y
+                }),
+                ShapeKind::Ellipse => coverage.fill(area, |pos| {
+                    let pos = (pos - rect.center()) / (rect.size() / 2.0);
+                    vec2::dot(pos, pos) <= 1.0
+                }),
+            }
+        }
+        for segment in outline.windows(2) {
+            self.brush.cover_line(coverage, segment[0], segment[1]);
+        }
+    }
+}
+
+impl Tool for Shape {
+    type Stroke = ShapeStroke;
+    fn start(&mut self, state: &mut State, ray: Ray) -> Option<ShapeStroke> {
+        let idx = state.selected.active()?;
+        let plane = &state.model.planes[idx];
+        if !plane.editable() {
+            return None;
+        }
+        let raycast = plane.raycast(state.model.world_transform(idx), ray)?;
+        let pos = self.round_pos(raycast.texture_pos);
+        Some(ShapeStroke {
+            plane: idx,
+            start: pos,
+            end: pos,
+        })
+    }
+    fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {
+        let plane = &state.model.planes[stroke.plane];
+        if let Some(raycast) = plane.raycast(state.model.world_transform(stroke.plane), ray) {
+            stroke.end = self.round_pos(raycast.texture_pos);
+        }
+    }
+    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        let Some(plane) = state.model.planes.get(stroke.plane) else {
+            return;
+        };
+        let area = self.bounds(stroke.start, stroke.end);
+        let mut coverage = Coverage::default();
+        self.cover_shape(&mut coverage, stroke.start, stroke.end);
+        let mut texture = plane.texture.borrow_mut();
+        let mut edit = TextureEdit::new();
+        edit.touch(&texture, area);
+        self.brush
+            .composite(&mut texture, &edit, &coverage, area, state.color);
+        drop(texture);
+        if let Some(command) = Command::paint(&state.model, stroke.plane, edit) {
+            state.history.push(&state.model, command);
+        }
+    }
+
+    fn draw(
+        &mut self,
+        framebuffer: &mut ugli::Framebuffer,
+        ray: Option<Ray>,
+        stroke: Option<&mut Self::Stroke>,
+        state: &mut State,
+        ui_camera: &dyn AbstractCamera2d,
+        status_pos: mat3<f32>,
+    ) {
+        let preview = match stroke {
+            Some(stroke) => Some((stroke.plane, stroke.start, stroke.end)),
+            None => ray.and_then(|ray| {
+                let idx = state.selected.active()?;
+                let raycast =
+                    state.model.planes[idx].raycast(state.model.world_transform(idx), ray)?;
+                let pos = self.round_pos(raycast.texture_pos);
+                Some((idx, pos, pos))
+            }),
+        };
+        if let Some((idx, start, end)) = preview {
+            let key = (
+                start,
+                end,
+                state.color,
+                self.filled,
+                self.brush.draw_width(),
+            );
+            if self
+                .preview
+                .as_ref()
+                .map_or(true, |(old, _plane)| *old != key)
+            {
+                let mut coverage = Coverage::default();
+                self.cover_shape(&mut coverage, start, end);
+                let area = self.bounds(start, end);
+                let texture = Texture::from_pixels(
+                    self.ctx.geng.ugli(),
+                    &coverage.to_pixels(area, state.color),
+                    area.min,
+                );
+                self.preview = Some((key, Plane::new(texture, mat4::identity())));
+            }
+            let (_key, plane) = self.preview.as_ref().unwrap();
+            let transform = state.model.world_transform(idx);
+            self.ctx
+                .draw_preview(plane, transform, framebuffer, &state.camera, true);
+        }
+
+        let kind = match self.kind {
+            ShapeKind::Line => "line",
+            ShapeKind::Rectangle => "rectangle",
+            ShapeKind::Ellipse => "ellipse",
+        };
+        let text = match self.filled && self.kind != ShapeKind::Line {
+            true => format!("filled {kind}"),
+            false => kind.to_owned(),
+        };
+        self.ctx.geng.default_font().draw(
+            framebuffer,
+            ui_camera,
+            &text,
+            vec2::splat(geng::TextAlign::CENTER),
+            status_pos,
+            state.color,
+        );
+    }
+
+    fn handle_event(&mut self, event: geng::Event) {
+        // Blend mode cycling adds a modifier to the filled key
+        if self.ctx.keys.shape.filled.matches(&event, &self.ctx)
+            && !self.ctx.keys.brush.blend_mode.matches(&event, &self.ctx)
+        {
+            self.filled = !self.filled;
+        }
+        self.brush.handle_event(event);
+    }
+}
@@ -2,7 +2,8 @@ use super::*;
 
 pub struct Transform {
     ctx: Ctx,
-    origin: Option<vec2<f32>>,
+    /// In the local space of the transformed node
+    origin: Option<vec3<f32>>,
 }
 
 impl Transform {
@@ -12,12 +13,26 @@ impl Transform {
             origin: None,
         }
     }
+
+    /// Selected group as a whole, or the active plane
+    fn node(&self, state: &State) -> Option<Node> {
+        match state.selected.group() {
+            Some(group) => Some(Node::Group(group)),
+            None => state.selected.active().map(Node::Plane),
+        }
+    }
 }
 
 pub struct TransformStroke {
+    node: Node,
     mode: gizmo::TransformMode,
     start_point: vec3<f32>,
+    /// World transform at the start of the stroke
     original_transform: mat4<f32>,
+    /// Other selected planes follow the active one, with their original world transforms
+    others: Vec<(Node, mat4<f32>)>,
+    /// Local transforms at the start of the stroke to record in history
+    original_local: Vec<(Node, mat4<f32>)>,
 }
 
 fn closest_point_to_line(ray: Ray, line: Ray) -> vec3<f32> {
@@ -36,19 +51,26 @@ impl Tool for Transform {
     type Stroke = TransformStroke;
 
     fn start(&mut self, state: &mut State, ray: Ray) -> Option<Self::Stroke> {
-        let Some(idx) = state.selected else {
-            return None;
-        };
-        let plane = &state.model.planes[idx];
+        let node = self.node(state)?;
+        if let Node::Plane(idx) = node {
+            if !state.model.planes[idx].editable() {
+                return None;
+            }
+        }
+        let node_transform = state.model.node_world_transform(node);
         match self.origin {
             None => {
-                if let Some(raycast) = plane.raycast(ray) {
+                // Origin is always picked on the active plane, even when moving its group
+                let idx = state.selected.active()?;
+                let plane_transform = state.model.world_transform(idx);
+                if let Some(raycast) = state.model.planes[idx].raycast(plane_transform, ray) {
                     let pos = self.ctx.round_pos(raycast.texture_pos);
-                    self.origin = Some(pos);
+                    let world_pos = plane_transform * pos.extend(0.0).extend(1.0);
+                    self.origin = Some((node_transform.inverse() * world_pos).into_3d());
                 }
             }
             Some(origin) => {
-                let transform = plane.transform * mat4::translate(origin.extend(0.0));
+                let transform = node_transform * mat4::translate(origin);
                 let mode = self
                     .ctx
                     .gizmo
@@ -69,10 +91,29 @@ impl Tool for Transform {
                         ray.from + ray.dir * t
                     }
                 };
+                let others: Vec<Node> = match node {
+                    Node::Group(_) => vec![],
+                    Node::Plane(idx) => state
+                        .selected
+                        .iter()
+                        .filter(|&other| other != idx)
+                        .filter(|&other| state.model.planes[other].editable())
+                        .map(Node::Plane)
+                        .collect(),
+                };
                 return Some(TransformStroke {
+                    node,
                     mode,
                     start_point,
-                    original_transform: plane.transform,
+                    original_transform: node_transform,
+                    original_local: std::iter::once(node)
+                        .chain(others.iter().copied())
+                        .map(|node| (node, state.model.node_transform(node)))
+                        .collect(),
+                    others: others
+                        .into_iter()
+                        .map(|node| (node, state.model.node_world_transform(node)))
+                        .collect(),
                 });
             }
         }
@@ -80,9 +121,7 @@ impl Tool for Transform {
     }
 
     fn resume(&mut self, stroke: &mut Self::Stroke, state: &mut State, ray: Ray) {
-        let plane = &mut state.model.planes[state.selected.unwrap()];
-        let origin =
-            (stroke.original_transform * self.origin.unwrap().extend(0.0).extend(1.0)).into_3d();
+        let origin = (stroke.original_transform * self.origin.unwrap().extend(1.0)).into_3d();
 
         let normalized_original_transform = {
             let x = stroke.original_transform.col(0).xyz().normalize();
@@ -113,14 +152,34 @@ impl Tool for Transform {
             }
         };
 
-        plane.transform = mat4::translate(origin)
+        let transform = mat4::translate(origin)
             * self
                 .ctx
                 .round_matrix(local_transform * normalized_original_transform)
-            * mat4::translate(-self.origin.unwrap().extend(0.0));
+            * mat4::translate(-self.origin.unwrap());
+        state.model.set_node_world_transform(stroke.node, transform);
+
+        let delta = transform * stroke.original_transform.inverse();
+        for &(other, original_transform) in &stroke.others {
+            state
+                .model
+                .set_node_world_transform(other, delta * original_transform);
+        }
     }
 
-    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {}
+    fn end(&mut self, stroke: Self::Stroke, state: &mut State, ray: Ray) {
+        let commands = stroke
+            .original_local
+            .into_iter()
+            .filter_map(|(node, old)| {
+                let new = state.model.node_transform(node);
+                (new != old).then_some(Command::Transform { node, old, new })
+            })
+            .collect();
+        if let Some(command) = Command::group(commands) {
+            state.history.push(&state.model, command);
+        }
+    }
 
     fn draw(
         &mut self,
@@ -132,12 +191,11 @@ impl Tool for Transform {
         status_pos: mat3<f32>,
     ) {
         if let Some(origin) = self.origin {
-            if let Some(idx) = state.selected {
-                let plane = &state.model.planes[idx];
+            if let Some(node) = self.node(state) {
                 self.ctx.gizmo.draw(
                     framebuffer,
                     &state.camera,
-                    plane.transform * mat4::translate(origin.extend(0.0)),
+                    state.model.node_world_transform(node) * mat4::translate(origin),
                 );
             }
         }